@@ -0,0 +1,90 @@
+//! Follows the finalized blocks of a node and stores the events logged by a
+//! `gona_stake` instance in an SQLite database. The last indexed block is
+//! checkpointed, so the indexer resumes where it stopped when restarted.
+mod node;
+mod store;
+mod sync;
+
+use anyhow::{Context, Error};
+use clap::Parser;
+use concordium_rust_sdk::{types::ContractAddress, v2};
+use std::{path::PathBuf, time::Duration};
+use store::Store;
+use tonic::transport::ClientTlsConfig;
+
+/// Command line flags.
+#[derive(clap::Parser, Debug)]
+#[clap(author, version, about)]
+struct App {
+    #[clap(
+        long = "node",
+        default_value = "https://grpc.testnet.concordium.com:20000",
+        help = "V2 API of the Concordium node."
+    )]
+    endpoint: v2::Endpoint,
+    #[clap(
+        long = "contract",
+        help = "Index of the gona_stake contract instance to index."
+    )]
+    contract: u64,
+    #[clap(
+        long = "db",
+        default_value = "gona_stake_events.sqlite",
+        help = "Path of the SQLite database the events are stored in."
+    )]
+    db: PathBuf,
+    #[clap(
+        long = "start-height",
+        default_value = "0",
+        help = "Height of the block to start from when the database has no checkpoint yet, e.g. \
+                the block the contract was initialized in."
+    )]
+    start_height: u64,
+    #[clap(
+        long = "poll-interval",
+        default_value = "5",
+        help = "Seconds to wait for new finalized blocks once caught up."
+    )]
+    poll_interval: u64,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Error> {
+    let app: App = App::parse();
+
+    let endpoint = if app
+        .endpoint
+        .uri()
+        .scheme()
+        .is_some_and(|x| *x == v2::Scheme::HTTPS)
+    {
+        app.endpoint
+            .tls_config(ClientTlsConfig::new())
+            .context("Unable to construct a TLS connection for the Concordium API.")?
+    } else {
+        app.endpoint
+    };
+
+    let mut client = v2::Client::new(endpoint)
+        .await
+        .context("Unable to establish connection to the node.")?;
+    let mut store = Store::open(&app.db)?;
+    let contract = ContractAddress::new(app.contract, 0);
+
+    loop {
+        let indexed = sync::index_finalized_blocks(
+            &mut client,
+            &mut store,
+            contract,
+            app.start_height.into(),
+        )
+        .await?;
+        if indexed > 0 {
+            println!(
+                "Indexed {indexed} blocks, up to height {:?}.",
+                store.checkpoint()?
+            );
+        }
+        tokio::time::sleep(Duration::from_secs(app.poll_interval)).await;
+    }
+}
@@ -0,0 +1,67 @@
+use anyhow::{Context, Error};
+use concordium_rust_sdk::{
+    smart_contracts::common::Timestamp,
+    types::{AbsoluteBlockHeight, BlockItemSummary},
+    v2::{self, BlockIdentifier},
+};
+use futures::TryStreamExt;
+
+/// A finalized block as the indexer sees it.
+#[derive(Debug, Clone)]
+pub struct FinalizedBlock {
+    pub height: AbsoluteBlockHeight,
+    pub hash: String,
+    pub slot_time: Timestamp,
+    /// The outcomes of the transactions in the block.
+    pub summaries: Vec<BlockItemSummary>,
+}
+
+/// The queries the indexer makes to a node, so it can be run against a mock
+/// of the node in tests.
+pub trait Node {
+    /// The height of the last finalized block.
+    async fn last_finalized_height(&mut self) -> Result<AbsoluteBlockHeight, Error>;
+
+    /// The finalized block at `height`.
+    async fn finalized_block(
+        &mut self,
+        height: AbsoluteBlockHeight,
+    ) -> Result<FinalizedBlock, Error>;
+}
+
+impl Node for v2::Client {
+    async fn last_finalized_height(&mut self) -> Result<AbsoluteBlockHeight, Error> {
+        let info = self
+            .get_consensus_info()
+            .await
+            .context("Unable to get the consensus info.")?;
+        Ok(info.last_finalized_block_height)
+    }
+
+    async fn finalized_block(
+        &mut self,
+        height: AbsoluteBlockHeight,
+    ) -> Result<FinalizedBlock, Error> {
+        let info = self
+            .get_block_info(BlockIdentifier::AbsoluteHeight(height))
+            .await
+            .with_context(|| format!("Unable to get the block at height {height}."))?
+            .response;
+        let summaries = self
+            .get_block_transaction_events(info.block_hash)
+            .await
+            .context("Unable to get the transaction events.")?
+            .response
+            .try_collect()
+            .await
+            .context("Unable to read the transaction events.")?;
+        Ok(FinalizedBlock {
+            height,
+            hash: info.block_hash.to_string(),
+            slot_time: Timestamp::from_timestamp_millis(
+                info.block_slot_time.timestamp_millis() as u64
+            ),
+            summaries,
+        })
+    }
+}
@@ -0,0 +1,146 @@
+use anyhow::{Context, Error};
+use concordium_rust_sdk::{
+    smart_contracts::common::{to_bytes, Timestamp},
+    types::AbsoluteBlockHeight,
+};
+use gona_stake::types::StakingEvent;
+use rusqlite::{params, Connection, OptionalExtension};
+use std::path::Path;
+
+/// A staking event logged by the contract, with where it was logged.
+///
+/// SQLite integers are signed, so heights and times are stored as `i64`.
+#[derive(Debug, PartialEq)]
+pub struct StoredEvent {
+    pub block_height: u64,
+    pub block_hash: String,
+    pub block_time: Timestamp,
+    pub transaction_hash: String,
+    /// The position of the event among the events of its transaction.
+    pub event_index: u32,
+    pub event: StakingEvent,
+}
+
+/// The SQLite database the indexer writes the events of the contract into.
+pub struct Store {
+    connection: Connection,
+}
+
+impl Store {
+    /// Opens the database at `path`, creating it if it does not exist yet.
+    pub fn open(path: &Path) -> Result<Store, Error> {
+        let connection = Connection::open(path)
+            .with_context(|| format!("Unable to open the database {}.", path.display()))?;
+        Store::new(connection)
+    }
+
+    /// A database that only lives in memory.
+    #[cfg(test)]
+    pub fn in_memory() -> Result<Store, Error> {
+        Store::new(Connection::open_in_memory()?)
+    }
+
+    fn new(connection: Connection) -> Result<Store, Error> {
+        connection
+            .execute_batch(
+                "CREATE TABLE IF NOT EXISTS checkpoint (
+                    id INTEGER PRIMARY KEY CHECK (id = 0),
+                    block_height INTEGER NOT NULL,
+                    block_hash TEXT NOT NULL
+                );
+                CREATE TABLE IF NOT EXISTS events (
+                    id INTEGER PRIMARY KEY AUTOINCREMENT,
+                    block_height INTEGER NOT NULL,
+                    block_hash TEXT NOT NULL,
+                    block_time INTEGER NOT NULL,
+                    transaction_hash TEXT NOT NULL,
+                    event_index INTEGER NOT NULL,
+                    event BLOB NOT NULL,
+                    description TEXT NOT NULL
+                );",
+            )
+            .context("Unable to create the database tables.")?;
+        Ok(Store { connection })
+    }
+
+    /// The height of the last block indexed, if any.
+    pub fn checkpoint(&self) -> Result<Option<AbsoluteBlockHeight>, Error> {
+        let height = self
+            .connection
+            .query_row(
+                "SELECT block_height FROM checkpoint WHERE id = 0",
+                [],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(height.map(|height: i64| (height as u64).into()))
+    }
+
+    /// Stores the events of a block and moves the checkpoint past it. Both
+    /// happen in one transaction, so an interrupted indexer resumes with the
+    /// block it did not finish.
+    pub fn insert_block(
+        &mut self,
+        height: AbsoluteBlockHeight,
+        block_hash: &str,
+        events: &[StoredEvent],
+    ) -> Result<(), Error> {
+        let transaction = self.connection.transaction()?;
+        for event in events {
+            transaction.execute(
+                "INSERT INTO events (block_height, block_hash, block_time, transaction_hash, \
+                 event_index, event, description) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    event.block_height as i64,
+                    event.block_hash,
+                    event.block_time.timestamp_millis() as i64,
+                    event.transaction_hash,
+                    event.event_index,
+                    to_bytes(&event.event),
+                    format!("{:?}", event.event),
+                ],
+            )?;
+        }
+        transaction.execute(
+            "INSERT INTO checkpoint (id, block_height, block_hash) VALUES (0, ?1, ?2) ON CONFLICT \
+             (id) DO UPDATE SET block_height = ?1, block_hash = ?2",
+            params![height.height as i64, block_hash],
+        )?;
+        transaction.commit()?;
+        Ok(())
+    }
+
+    /// All stored events in the order they were logged.
+    #[cfg(test)]
+    pub fn events(&self) -> Result<Vec<StoredEvent>, Error> {
+        use concordium_rust_sdk::smart_contracts::common::from_bytes;
+
+        let mut statement = self.connection.prepare(
+            "SELECT block_height, block_hash, block_time, transaction_hash, event_index, event \
+             FROM events ORDER BY id",
+        )?;
+        let rows = statement.query_map([], |row| {
+            Ok((
+                row.get::<_, i64>(0)?,
+                row.get(1)?,
+                row.get::<_, i64>(2)?,
+                row.get(3)?,
+                row.get(4)?,
+                row.get::<_, Vec<u8>>(5)?,
+            ))
+        })?;
+        let mut events = Vec::new();
+        for row in rows {
+            let (block_height, block_hash, block_time, transaction_hash, event_index, bytes) = row?;
+            events.push(StoredEvent {
+                block_height: block_height as u64,
+                block_hash,
+                block_time: Timestamp::from_timestamp_millis(block_time as u64),
+                transaction_hash,
+                event_index,
+                event: from_bytes(&bytes).context("Unable to decode a stored event.")?,
+            });
+        }
+        Ok(events)
+    }
+}
@@ -0,0 +1,188 @@
+use crate::{
+    node::{FinalizedBlock, Node},
+    store::{Store, StoredEvent},
+};
+use anyhow::{Context, Error};
+use concordium_rust_sdk::types::{AbsoluteBlockHeight, ContractAddress};
+use gona_stake::types::StakingEvent;
+
+/// Indexes the finalized blocks after the checkpoint of `store`, or from
+/// `start` on if nothing was indexed yet. Returns the number of blocks indexed.
+pub async fn index_finalized_blocks(
+    node: &mut impl Node,
+    store: &mut Store,
+    contract: ContractAddress,
+    start: AbsoluteBlockHeight,
+) -> Result<u64, Error> {
+    let last_finalized = node.last_finalized_height().await?;
+    let mut height = store.checkpoint()?.map_or(start, AbsoluteBlockHeight::next);
+    let mut indexed = 0;
+    while height <= last_finalized {
+        let block = node.finalized_block(height).await?;
+        let events = contract_events(&block, contract)?;
+        store.insert_block(height, &block.hash, &events)?;
+        height = height.next();
+        indexed += 1;
+    }
+    Ok(indexed)
+}
+
+/// Decodes the events `contract` logged in the transactions of `block`.
+pub fn contract_events(
+    block: &FinalizedBlock,
+    contract: ContractAddress,
+) -> Result<Vec<StoredEvent>, Error> {
+    let mut events = Vec::new();
+    for summary in &block.summaries {
+        let Some(logs) = summary.contract_update_logs() else {
+            continue;
+        };
+        let transaction_hash = summary.hash.to_string();
+        let logged = logs
+            .filter(|(address, _)| *address == contract)
+            .flat_map(|(_, logged)| logged);
+        for (event_index, event) in logged.enumerate() {
+            let event: StakingEvent = event
+                .parse()
+                .with_context(|| format!("Unable to decode an event of {transaction_hash}."))?;
+            events.push(StoredEvent {
+                block_height: block.height.height,
+                block_hash: block.hash.clone(),
+                block_time: block.slot_time,
+                transaction_hash: transaction_hash.clone(),
+                event_index: event_index as u32,
+                event,
+            });
+        }
+    }
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use concordium_rust_sdk::{
+        common::types::Amount,
+        id::types::AccountAddress,
+        smart_contracts::common::{to_bytes, Timestamp},
+        types::{
+            hashes::TransactionHash, smart_contracts::ContractEvent, AccountTransactionDetails,
+            AccountTransactionEffects, BlockItemSummary, BlockItemSummaryDetails,
+            ContractTraceElement, Energy, TransactionIndex,
+        },
+    };
+
+    const GONA_STAKE: ContractAddress = ContractAddress {
+        index: 7000,
+        subindex: 0,
+    };
+    const OTHER_CONTRACT: ContractAddress = ContractAddress {
+        index: 7001,
+        subindex: 0,
+    };
+
+    /// A node that serves the blocks it was given, finalizing them one by one.
+    struct MockNode {
+        blocks: Vec<FinalizedBlock>,
+        finalized: usize,
+    }
+
+    impl Node for MockNode {
+        async fn last_finalized_height(&mut self) -> Result<AbsoluteBlockHeight, Error> {
+            Ok((self.finalized as u64).into())
+        }
+
+        async fn finalized_block(
+            &mut self,
+            height: AbsoluteBlockHeight,
+        ) -> Result<FinalizedBlock, Error> {
+            let block = self
+                .blocks
+                .get(height.height as usize)
+                .context("No such block.")?;
+            Ok(block.clone())
+        }
+    }
+
+    /// A transaction in which `address` logged `events`.
+    fn update(hash: u8, address: ContractAddress, events: &[StakingEvent]) -> BlockItemSummary {
+        BlockItemSummary {
+            index: TransactionIndex { index: 0 },
+            energy_cost: Energy::from(0),
+            hash: TransactionHash::new([hash; 32]),
+            details: BlockItemSummaryDetails::AccountTransaction(AccountTransactionDetails {
+                cost: Amount::zero(),
+                sender: AccountAddress([0; 32]),
+                effects: AccountTransactionEffects::ContractUpdateIssued {
+                    effects: vec![ContractTraceElement::Interrupted {
+                        address,
+                        events: events
+                            .iter()
+                            .map(|event| ContractEvent::from(to_bytes(event)))
+                            .collect(),
+                    }],
+                },
+            }),
+        }
+    }
+
+    fn block(height: u64, summaries: Vec<BlockItemSummary>) -> FinalizedBlock {
+        FinalizedBlock {
+            height: height.into(),
+            hash: format!("block-{height}"),
+            slot_time: Timestamp::from_timestamp_millis(height * 1000),
+            summaries,
+        }
+    }
+
+    fn weight_changed(weight: u32) -> StakingEvent {
+        StakingEvent::WeightChanged { weight }
+    }
+
+    #[tokio::test]
+    async fn test_indexes_contract_events_and_resumes() {
+        let mut node = MockNode {
+            blocks: vec![
+                block(0, vec![]),
+                block(
+                    1,
+                    vec![
+                        update(1, GONA_STAKE, &[weight_changed(1), weight_changed(2)]),
+                        update(2, OTHER_CONTRACT, &[weight_changed(3)]),
+                    ],
+                ),
+                block(2, vec![update(3, GONA_STAKE, &[weight_changed(4)])]),
+            ],
+            finalized: 1,
+        };
+        let mut store = Store::in_memory().expect("store");
+
+        let indexed = index_finalized_blocks(&mut node, &mut store, GONA_STAKE, 0.into())
+            .await
+            .expect("index");
+        assert_eq!(indexed, 2);
+        assert_eq!(store.checkpoint().expect("checkpoint"), Some(1.into()));
+        let events = store.events().expect("events");
+        assert_eq!(
+            events
+                .iter()
+                .map(|stored| &stored.event)
+                .collect::<Vec<_>>(),
+            [&weight_changed(1), &weight_changed(2)],
+            "only the events of the staking contract are stored"
+        );
+        assert_eq!(events[1].event_index, 1);
+        assert_eq!(events[1].block_time, Timestamp::from_timestamp_millis(1000));
+
+        // the next run picks up from the checkpoint
+        node.finalized = 2;
+        let indexed = index_finalized_blocks(&mut node, &mut store, GONA_STAKE, 0.into())
+            .await
+            .expect("index");
+        assert_eq!(indexed, 1);
+        let events = store.events().expect("events");
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[2].event, weight_changed(4));
+        assert_eq!(events[2].block_hash, "block-2");
+    }
+}
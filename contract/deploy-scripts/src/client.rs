@@ -0,0 +1,372 @@
+//! A typed client for a `gona_stake` instance on chain.
+//!
+//! [`GonaStakeClient`] wraps the [`ContractClient`] of the SDK, so callers pass
+//! and get back the types of the contract instead of building
+//! `UpdateContractPayload`s by hand. Updates are dry-run before they are sent,
+//! and a reject by the contract is returned as the [`StakingError`] it
+//! rejected with.
+use concordium_cis2::{
+    AdditionalData, Receiver, TokenAmountU64, TokenIdUnit, Transfer, TransferParams,
+};
+use concordium_rust_sdk::{
+    contract_client::{ContractClient, ContractUpdateBuilder, ContractUpdateError},
+    id::types::AccountAddress,
+    indexer::ContractUpdateInfo,
+    smart_contracts::common::{
+        self as contracts_common, Address, Amount, ExceedsParameterSize, NewReceiveNameError,
+        OwnedEntrypointName, ParseError,
+    },
+    types::{
+        smart_contracts::OwnedContractName, transactions::ExactSizeTransactionSigner,
+        ContractAddress, ExecutionTree, RejectReason,
+    },
+    v2::{self, BlockIdentifier, QueryError},
+};
+use gona_stake::types::{
+    PositionId, RewardResult, StakeQuery, Staker, StakingError, StakingEvent, UnstakeParam,
+};
+
+/// Marks the [`ContractClient`] of a `gona_stake` instance.
+#[derive(Debug)]
+pub enum GonaStake {}
+
+/// Marks the [`ContractClient`] of the CIS-2 token staked with `gona_stake`.
+#[derive(Debug)]
+pub enum StakedToken {}
+
+/// An error of a call made through [`GonaStakeClient`].
+#[derive(Debug, thiserror::Error)]
+pub enum ClientError {
+    /// The contract rejected the call with one of its errors.
+    #[error("gona_stake rejected with {0:?}.")]
+    Staking(StakingError),
+    /// The call was rejected for a reason outside of the errors of the
+    /// contract, e.g. by the token contract or for running out of energy.
+    #[error("The call was rejected: {0:?}.")]
+    Rejected(RejectReason),
+    #[error("Invalid entrypoint name: {0}")]
+    InvalidName(#[from] NewReceiveNameError),
+    #[error("Unable to parse the response: {0}")]
+    InvalidResponse(#[from] ParseError),
+    #[error("Query failed: {0}")]
+    Query(#[from] QueryError),
+    #[error("Parameter is too large: {0}")]
+    Parameter(#[from] ExceedsParameterSize),
+}
+
+impl From<RejectReason> for ClientError {
+    fn from(reason: RejectReason) -> Self {
+        ClientError::Rejected(reason)
+    }
+}
+
+impl From<ContractUpdateError> for ClientError {
+    fn from(error: ContractUpdateError) -> Self {
+        match error {
+            ContractUpdateError::Query(error) => ClientError::Query(error),
+            ContractUpdateError::Failed(reason) => ClientError::Rejected(reason),
+        }
+    }
+}
+
+/// The [`StakingError`] with reject code `code`. The codes count down from
+/// `-1` in the order of the variants.
+pub fn staking_error(code: i32) -> Option<StakingError> {
+    let index = u8::try_from(-i64::from(code) - 1).ok()?;
+    contracts_common::from_bytes(&[index]).ok()
+}
+
+/// Turns a reject by the instance at `address` into the [`StakingError`] it
+/// rejected with.
+pub fn staking_reject(address: ContractAddress, error: ClientError) -> ClientError {
+    match error {
+        ClientError::Rejected(RejectReason::RejectedReceive {
+            reject_reason,
+            contract_address,
+            ..
+        }) if contract_address == address => match staking_error(reject_reason) {
+            Some(staking_error) => ClientError::Staking(staking_error),
+            None => error,
+        },
+        error => error,
+    }
+}
+
+/// The events the instance at `address` logged in `tree`.
+pub fn staking_events(
+    tree: &ExecutionTree,
+    address: ContractAddress,
+) -> Result<Vec<StakingEvent>, ParseError> {
+    tree.events()
+        .filter(|(logged_by, ..)| *logged_by == address)
+        .flat_map(|(_, _, events)| events)
+        .map(|event| event.parse())
+        .collect()
+}
+
+/// A finalized update sent through [`GonaStakeClient`].
+pub struct UpdateOutcome {
+    pub info: ContractUpdateInfo,
+    /// The events `gona_stake` logged in the update.
+    pub events: Vec<StakingEvent>,
+}
+
+impl UpdateOutcome {
+    /// The position opened by a stake.
+    pub fn staked_position(&self) -> Option<PositionId> {
+        self.events.iter().find_map(|event| match event {
+            StakingEvent::Staked { position, .. } => Some(*position),
+            _ => None,
+        })
+    }
+}
+
+/// A client for a `gona_stake` instance staking the CIS-2 token at
+/// `token_address`.
+#[derive(Debug, Clone)]
+pub struct GonaStakeClient {
+    pub contract: ContractClient<GonaStake>,
+    pub token_address: ContractAddress,
+}
+
+impl GonaStakeClient {
+    pub fn new(
+        client: v2::Client,
+        address: ContractAddress,
+        token_address: ContractAddress,
+    ) -> Self {
+        let contract = ContractClient::new(
+            client,
+            address,
+            OwnedContractName::new_unchecked("init_gona_stake".to_string()),
+        );
+        GonaStakeClient {
+            contract,
+            token_address,
+        }
+    }
+
+    /// The position `position`, if it is open.
+    pub async fn get_stake_info(
+        &mut self,
+        position: PositionId,
+    ) -> Result<StakeQuery, ClientError> {
+        self.view("get_stake_info", &position).await
+    }
+
+    /// The rewards `position` has earned so far.
+    pub async fn calculate_rewards(
+        &mut self,
+        position: PositionId,
+    ) -> Result<RewardResult, ClientError> {
+        self.view("calculate_rewards", &position).await
+    }
+
+    /// The tokens left to pay rewards with.
+    pub async fn view_reward_volume(&mut self) -> Result<u64, ClientError> {
+        self.view("view_reward_volume", &()).await
+    }
+
+    /// Stakes `amount` tokens from the account `sender` by transferring them
+    /// into the `stake` hook of the contract. A reject by `gona_stake` surfaces
+    /// as a reject of the token contract, which is returned as
+    /// [`ClientError::Rejected`].
+    pub async fn stake(
+        &mut self,
+        signer: &impl ExactSizeTransactionSigner,
+        sender: AccountAddress,
+        amount: u64,
+        referrer: Option<Staker>,
+    ) -> Result<UpdateOutcome, ClientError> {
+        let data = referrer.map_or_else(Vec::new, |referrer| contracts_common::to_bytes(&referrer));
+        let transfer = Transfer {
+            token_id: TokenIdUnit(),
+            amount: TokenAmountU64(amount),
+            from: Address::Account(sender),
+            to: Receiver::Contract(
+                self.contract.address,
+                OwnedEntrypointName::new_unchecked("stake".to_string()),
+            ),
+            data: AdditionalData::from(data),
+        };
+        let mut token: ContractClient<StakedToken> =
+            ContractClient::create(self.contract.client.clone(), self.token_address).await?;
+        let update = token
+            .dry_run_update::<_, ClientError>(
+                "transfer",
+                Amount::zero(),
+                sender,
+                &TransferParams::from(vec![transfer]),
+            )
+            .await?;
+        self.send(signer, update).await
+    }
+
+    /// Unstakes from a position of the account `sender`.
+    pub async fn unstake(
+        &mut self,
+        signer: &impl ExactSizeTransactionSigner,
+        sender: AccountAddress,
+        param: &UnstakeParam,
+    ) -> Result<UpdateOutcome, ClientError> {
+        self.update(signer, sender, "unstake", param).await
+    }
+
+    /// Changes the weight rewards are calculated with. Only the admin may.
+    pub async fn change_weight(
+        &mut self,
+        signer: &impl ExactSizeTransactionSigner,
+        sender: AccountAddress,
+        weight: u32,
+    ) -> Result<UpdateOutcome, ClientError> {
+        self.update(signer, sender, "change_weight", &weight).await
+    }
+
+    /// Pauses the contract. Only the admin may.
+    pub async fn set_paused(
+        &mut self,
+        signer: &impl ExactSizeTransactionSigner,
+        sender: AccountAddress,
+    ) -> Result<UpdateOutcome, ClientError> {
+        self.update(signer, sender, "set_paused", &()).await
+    }
+
+    async fn view<P: contracts_common::Serial, A: contracts_common::Deserial>(
+        &mut self,
+        entrypoint: &str,
+        parameter: &P,
+    ) -> Result<A, ClientError> {
+        let result = self
+            .contract
+            .view::<P, A, ClientError>(entrypoint, parameter, BlockIdentifier::LastFinal)
+            .await;
+        result.map_err(|error| staking_reject(self.contract.address, error))
+    }
+
+    async fn update<P: contracts_common::Serial>(
+        &mut self,
+        signer: &impl ExactSizeTransactionSigner,
+        sender: AccountAddress,
+        entrypoint: &str,
+        parameter: &P,
+    ) -> Result<UpdateOutcome, ClientError> {
+        let update = self
+            .contract
+            .dry_run_update::<P, ClientError>(entrypoint, Amount::zero(), sender, parameter)
+            .await
+            .map_err(|error| staking_reject(self.contract.address, error))?;
+        self.send(signer, update).await
+    }
+
+    /// Sends a dry-run update and waits for it to be finalized.
+    async fn send(
+        &self,
+        signer: &impl ExactSizeTransactionSigner,
+        update: ContractUpdateBuilder,
+    ) -> Result<UpdateOutcome, ClientError> {
+        let handle = update.send(signer).await?;
+        let info = handle
+            .wait_for_finalization()
+            .await
+            .map_err(|error| staking_reject(self.contract.address, error.into()))?;
+        let events = staking_events(&info.execution_tree, self.contract.address)?;
+        Ok(UpdateOutcome { info, events })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use concordium_rust_sdk::{
+        smart_contracts::common::{to_bytes, OwnedParameter, OwnedReceiveName},
+        types::{
+            execution_tree,
+            smart_contracts::{ContractEvent, InstanceUpdatedEvent, WasmVersion},
+            ContractTraceElement,
+        },
+    };
+
+    const GONA_STAKE: ContractAddress = ContractAddress {
+        index: 7000,
+        subindex: 0,
+    };
+    const TOKEN: ContractAddress = ContractAddress {
+        index: 7001,
+        subindex: 0,
+    };
+
+    #[test]
+    fn test_reject_codes_map_to_staking_errors() {
+        assert_eq!(staking_error(-1), Some(StakingError::StakingNotFound));
+        assert_eq!(staking_error(-14), Some(StakingError::SenderIsNotAdmin));
+        assert_eq!(staking_error(-20), Some(StakingError::NoPendingRewards));
+        assert_eq!(staking_error(0), None);
+        assert_eq!(staking_error(-1000), None);
+        assert_eq!(staking_error(i32::MIN), None);
+
+        let rejected_by = |contract_address| {
+            ClientError::Rejected(RejectReason::RejectedReceive {
+                reject_reason: -14,
+                contract_address,
+                receive_name: OwnedReceiveName::new_unchecked("gona_stake.change_weight".into()),
+                parameter: OwnedParameter::empty(),
+            })
+        };
+        assert!(matches!(
+            staking_reject(GONA_STAKE, rejected_by(GONA_STAKE)),
+            ClientError::Staking(StakingError::SenderIsNotAdmin)
+        ));
+        assert!(matches!(
+            staking_reject(GONA_STAKE, rejected_by(TOKEN)),
+            ClientError::Rejected(_)
+        ));
+    }
+
+    fn update(
+        address: ContractAddress,
+        entrypoint: &str,
+        events: &[StakingEvent],
+    ) -> ContractTraceElement {
+        ContractTraceElement::Updated {
+            data: InstanceUpdatedEvent {
+                contract_version: WasmVersion::V1,
+                address,
+                instigator: Address::Contract(TOKEN),
+                amount: Amount::zero(),
+                message: OwnedParameter::empty(),
+                receive_name: OwnedReceiveName::new_unchecked(entrypoint.to_string()),
+                events: events
+                    .iter()
+                    .map(|event| ContractEvent::from(to_bytes(event)))
+                    .collect(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_events_of_the_instance_are_decoded() {
+        // The token contract is interrupted by its call to the `stake` hook.
+        let tree = execution_tree(vec![
+            ContractTraceElement::Interrupted {
+                address: TOKEN,
+                events: vec![],
+            },
+            update(
+                GONA_STAKE,
+                "gona_stake.stake",
+                &[StakingEvent::WeightChanged { weight: 3 }],
+            ),
+            ContractTraceElement::Resumed {
+                address: TOKEN,
+                success: true,
+            },
+            update(TOKEN, "gona_token.transfer", &[]),
+        ])
+        .expect("execution tree");
+        assert_eq!(
+            staking_events(&tree, GONA_STAKE).expect("events"),
+            [StakingEvent::WeightChanged { weight: 3 }]
+        );
+        assert_eq!(staking_events(&tree, TOKEN).expect("events"), []);
+    }
+}
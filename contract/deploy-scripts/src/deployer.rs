@@ -323,6 +323,26 @@ impl Deployer {
         }
     }
 
+    /// A function to invoke an entrypoint of a contract without sending a
+    /// transaction, e.g. to query a view function or to dry-run an update.
+    ///
+    /// The result of the invocation is returned by this function, whether the
+    /// contract succeeded or rejected.
+    pub async fn invoke_contract(
+        &mut self,
+        payload: UpdateContractPayload,
+    ) -> Result<InvokeContractResult, Error> {
+        let context =
+            ContractContext::new_from_payload(self.key.address, DEFAULT_INVOKE_ENERGY, payload);
+
+        let result = self
+            .client
+            .invoke_instance(&BlockIdentifier::LastFinal, &context)
+            .await?;
+
+        Ok(result.response)
+    }
+
     /// A function to get the next nonce of the wallet account.
     pub async fn get_nonce(
         &mut self,
@@ -0,0 +1,3 @@
+//! Rust clients of the `gona_stake` contract, shared by the scripts in this
+//! crate.
+pub mod client;
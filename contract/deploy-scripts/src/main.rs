@@ -1,7 +1,8 @@
 #![allow(unused_imports, unused_variables)]
 pub mod deployer;
+pub mod schema;
 
-use anyhow::{Context, Error};
+use anyhow::{bail, Context, Error};
 use clap::Parser;
 use concordium_rust_sdk::{
     common::types::Amount,
@@ -10,13 +11,14 @@ use concordium_rust_sdk::{
         types::{OwnedContractName, OwnedParameter, OwnedReceiveName},
     },
     types::{
-        smart_contracts::{ModuleReference, WasmModule},
+        smart_contracts::{InvokeContractResult, ModuleReference, WasmModule},
         transactions::{self, send::GivenEnergy, InitContractPayload},
         ContractAddress,
     },
-    v2,
+    v2::{self, BlockIdentifier},
 };
 use deployer::{DeployResult, Deployer, InitResult};
+use schema::ContractSchema;
 use gona_stake::*;
 use std::{
     io::Cursor,
@@ -55,6 +57,59 @@ struct App {
                 ./myPath/default.wasm.v1 --module ./default2.wasm.v1)."
     )]
     module: Vec<PathBuf>,
+    #[clap(
+        long = "smart-wallet",
+        default_value = "9833",
+        help = "Index of a smart wallet contract public keys are allowed to stake from. Use this \
+                flag several times to trust several smart wallets (e.g. --smart-wallet 9833 \
+                --smart-wallet 9900)."
+    )]
+    smart_wallets: Vec<u64>,
+    #[clap(
+        long = "init-param",
+        help = "Path to a JSON file with the `InitParam` to initialize the contract with. It is \
+                serialized through the schema embedded in the module. Defaults to the example \
+                parameter in this script."
+    )]
+    init_param: Option<PathBuf>,
+    #[clap(
+        long = "contract",
+        help = "Index of an existing gona_stake instance to call instead of initializing a new \
+                one."
+    )]
+    contract: Option<u64>,
+    #[clap(
+        long = "update",
+        conflicts_with = "invoke",
+        help = "Entrypoint to send an update transaction to (e.g. --update unstake)."
+    )]
+    update: Option<String>,
+    #[clap(
+        long = "invoke",
+        help = "Entrypoint to invoke without sending a transaction, printing its return value as \
+                JSON (e.g. --invoke view_settings)."
+    )]
+    invoke: Option<String>,
+    #[clap(
+        long = "param",
+        help = "Path to a JSON file with the parameter of the `--update` or `--invoke` \
+                entrypoint. Omit it for entrypoints that take no parameter."
+    )]
+    param: Option<PathBuf>,
+}
+
+/// Reads the schema of the module with reference `module_reference` from the
+/// chain.
+async fn get_contract_schema(
+    client: &mut v2::Client,
+    module_reference: ModuleReference,
+) -> Result<ContractSchema, Error> {
+    let module = client
+        .get_module_source(&module_reference, BlockIdentifier::LastFinal)
+        .await
+        .context("Unable to get the module source.")?
+        .response;
+    ContractSchema::from_module(&module)
 }
 
 /// Main function: It deploys to chain all wasm modules from the command line
@@ -69,7 +124,7 @@ async fn main() -> Result<(), Error> {
         .endpoint
         .uri()
         .scheme()
-        .map_or(false, |x| *x == concordium_rust_sdk::v2::Scheme::HTTPS)
+        .is_some_and(|x| *x == concordium_rust_sdk::v2::Scheme::HTTPS)
     {
         app.endpoint
             .tls_config(ClientTlsConfig::new())
@@ -106,39 +161,121 @@ async fn main() -> Result<(), Error> {
     // here.
 
     // You can easily import a type from the smart contract like so:
-    use gona_stake::types::InitParam;  // Example
+    use gona_stake::types::{InitParam, StakeLimits};  // Example
     let signer = <contracts_common::AccountAddress as std::str::FromStr>::from_str(
         "36J5gb5QVYBvbda4cZkagN4LvVCXejyX8ScuEx8xyAQckVjBMA",
     )?;
 
-    let param = InitParam {
-        admin: contracts_common::Address::Account(deployer.key.address),
-        decimals: 6,
-        token_address: ContractAddress {
-            index: 10300,
-            subindex: 0,
-        },
-        weight: 8500,
-        smart_wallet: ContractAddress {
-            index: 9833,
-            subindex: 0,
-        },
+    let contract_address = match app.contract {
+        Some(index) => ContractAddress::new(index, 0),
+        None => {
+            let param = match &app.init_param {
+                Some(path) => {
+                    let schema =
+                        get_contract_schema(&mut deployer.client, modules_deployed[0]).await?;
+                    schema.init_param(&schema::read_json(path)?)?
+                }
+                None => {
+                    let param = InitParam {
+                        admin: contracts_common::Address::Account(deployer.key.address),
+                        decimals: 6,
+                        token_address: ContractAddress {
+                            index: 10300,
+                            subindex: 0,
+                        },
+                        weight: 8500,
+                        smart_wallets: app
+                            .smart_wallets
+                            .iter()
+                            .map(|&index| ContractAddress { index, subindex: 0 })
+                            .collect(),
+                        limits: StakeLimits {
+                            min_stake: 1000,
+                            dust_threshold: 1000,
+                            max_per_staker: None,
+                            pool_capacity: None,
+                        },
+                    };
+                    OwnedParameter::from_serial(&param)? // Example
+                }
+            };
+
+            let init_method_name: &str = "init_gona_stake"; // Example
+
+            let payload = InitContractPayload {
+                init_name: OwnedContractName::new(init_method_name.into())?,
+                amount: Amount::from_micro_ccd(0),
+                mod_ref: modules_deployed[0],
+                param,
+            }; // Example
+
+            let init_result: InitResult = deployer
+                .init_contract(payload, None, None)
+                .await
+                .context("Failed to initialize the contract.")?; // Example
+
+            init_result.contract_address
+        }
     };
-    let param = OwnedParameter::from_serial(&param)?; // Example
 
-    let init_method_name: &str = "init_gona_stake"; // Example
+    // Call an entrypoint of the contract with a parameter given as JSON. The
+    // call is invoked first, so a reject is reported without paying for a
+    // transaction.
+    if let Some(entrypoint) = app.update.as_ref().or(app.invoke.as_ref()) {
+        let instance = deployer
+            .client
+            .get_instance_info(contract_address, BlockIdentifier::LastFinal)
+            .await
+            .context("Unable to get the contract instance.")?
+            .response;
+        let schema = get_contract_schema(&mut deployer.client, instance.source_module()).await?;
+        let param = app.param.as_deref().map(schema::read_json).transpose()?;
 
-    let payload = InitContractPayload {
-        init_name: OwnedContractName::new(init_method_name.into())?,
-        amount: Amount::from_micro_ccd(0),
-        mod_ref: modules_deployed[0],
-        param,
-    }; // Example
+        let update_payload = transactions::UpdateContractPayload {
+            amount: Amount::zero(),
+            address: contract_address,
+            receive_name: OwnedReceiveName::new(format!(
+                "{}.{entrypoint}",
+                schema::CONTRACT_NAME
+            ))?,
+            message: schema.receive_param(entrypoint, param.as_ref())?,
+        };
 
-    let init_result: InitResult = deployer
-        .init_contract(payload, None, None)
-        .await
-        .context("Failed to initialize the contract.")?; // Example
+        match deployer.invoke_contract(update_payload.clone()).await? {
+            InvokeContractResult::Failure {
+                return_value,
+                reason,
+                ..
+            } => {
+                let reject =
+                    return_value.and_then(|bytes| schema.reject_reason(entrypoint, &bytes.value));
+                match reject {
+                    Some(reject) => bail!("`{entrypoint}` rejected with {reject}."),
+                    None => bail!("`{entrypoint}` rejected: {reason:?}."),
+                }
+            }
+            InvokeContractResult::Success {
+                return_value,
+                mut used_energy,
+                ..
+            } => {
+                if app.invoke.is_some() {
+                    let json = match return_value {
+                        Some(bytes) => schema.return_value(entrypoint, &bytes.value)?,
+                        None => serde_json::Value::Null,
+                    };
+                    println!("{}", serde_json::to_string_pretty(&json)?);
+                } else {
+                    // We add 100 energy to be safe.
+                    used_energy.energy += 100;
+                    deployer
+                        .update_contract(update_payload, Some(GivenEnergy::Add(used_energy)), None)
+                        .await
+                        .context("Failed to update the contract.")?;
+                }
+            }
+        }
+    }
 
     // // Create a successful transaction.
     // // The input parameter to the receive function is in this example a bool.
@@ -0,0 +1,214 @@
+use anyhow::{bail, Context, Error};
+use concordium_rust_sdk::{
+    smart_contracts::{
+        common::{
+            schema::{VersionedModuleSchema, VersionedSchemaError},
+            Cursor, OwnedParameter,
+        },
+        engine::utils::get_embedded_schema_v1,
+        types::WasmModule,
+    },
+    types::smart_contracts::WasmVersion,
+};
+use serde_json::Value;
+use std::path::Path;
+
+/// The name of the contract in the module.
+pub const CONTRACT_NAME: &str = "gona_stake";
+
+/// The schema embedded in the `gona_stake` module. It turns the JSON
+/// parameters given on the command line into the bytes the contract expects,
+/// and return values back into JSON.
+pub struct ContractSchema {
+    schema: VersionedModuleSchema,
+}
+
+impl ContractSchema {
+    /// Reads the schema embedded in `module`.
+    pub fn from_module(module: &WasmModule) -> Result<ContractSchema, Error> {
+        if module.version != WasmVersion::V1 {
+            bail!("Only V1 modules embed the schema used by this tool.");
+        }
+        let schema = get_embedded_schema_v1(module.source.as_ref())
+            .context("The module does not embed a schema.")?;
+        Ok(ContractSchema { schema })
+    }
+
+    /// The parameter of `init_gona_stake` described by `json`.
+    pub fn init_param(&self, json: &Value) -> Result<OwnedParameter, Error> {
+        let ty = self
+            .schema
+            .get_init_param_schema(CONTRACT_NAME)
+            .context("The schema has no parameter for the init function.")?;
+        let bytes = ty
+            .serial_value(json)
+            .context("The JSON does not match the parameter of the init function.")?;
+        Ok(OwnedParameter::try_from(bytes)?)
+    }
+
+    /// The parameter of `entrypoint` described by `json`. Entrypoints that
+    /// take no parameter are called with `None`.
+    pub fn receive_param(
+        &self,
+        entrypoint: &str,
+        json: Option<&Value>,
+    ) -> Result<OwnedParameter, Error> {
+        let ty = match self
+            .schema
+            .get_receive_param_schema(CONTRACT_NAME, entrypoint)
+        {
+            Ok(ty) => ty,
+            Err(VersionedSchemaError::NoParamsInReceive) => {
+                if json.is_some() {
+                    bail!("`{entrypoint}` takes no parameter.");
+                }
+                return Ok(OwnedParameter::empty());
+            }
+            Err(error) => {
+                return Err(error).with_context(|| format!("Unknown entrypoint `{entrypoint}`."))
+            }
+        };
+        let Some(json) = json else {
+            bail!("`{entrypoint}` expects a parameter.");
+        };
+        let bytes = ty
+            .serial_value(json)
+            .with_context(|| format!("The JSON does not match the parameter of `{entrypoint}`."))?;
+        Ok(OwnedParameter::try_from(bytes)?)
+    }
+
+    /// The value returned by `entrypoint` as JSON.
+    pub fn return_value(&self, entrypoint: &str, bytes: &[u8]) -> Result<Value, Error> {
+        let ty = self
+            .schema
+            .get_receive_return_value_schema(CONTRACT_NAME, entrypoint)
+            .with_context(|| format!("The schema has no return value for `{entrypoint}`."))?;
+        let json = ty
+            .to_json(&mut Cursor::new(bytes))
+            .with_context(|| format!("Unable to decode the return value of `{entrypoint}`."))?;
+        Ok(json)
+    }
+
+    /// The reject reason of `entrypoint` as JSON, if the contract describes
+    /// it. Rejects from outside the contract, e.g. failing to parse the
+    /// parameter, have codes the schema does not cover.
+    pub fn reject_reason(&self, entrypoint: &str, bytes: &[u8]) -> Option<Value> {
+        let ty = self
+            .schema
+            .get_receive_error_schema(CONTRACT_NAME, entrypoint)
+            .ok()?;
+        ty.to_json(&mut Cursor::new(bytes)).ok()
+    }
+}
+
+/// Reads a JSON file given on the command line.
+pub fn read_json(path: &Path) -> Result<Value, Error> {
+    let file = std::fs::read(path)
+        .with_context(|| format!("Could not read the JSON file {}.", path.display()))?;
+    serde_json::from_slice(&file)
+        .with_context(|| format!("The file {} is not valid JSON.", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use concordium_rust_sdk::smart_contracts::common::{
+        to_bytes, AccountAddress, Address, ContractAddress,
+    };
+    use gona_stake::types::{InitParam, RewardResult, StakeLimits, StakingError, UnstakeParam};
+    use serde_json::json;
+
+    /// The module built by `cargo concordium build --out dist/stake.wasm.v1`.
+    fn schema() -> ContractSchema {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("../dist/stake.wasm.v1");
+        let module = crate::get_wasm_module(&path).expect("Module exists");
+        ContractSchema::from_module(&module).expect("Module embeds a schema")
+    }
+
+    #[test]
+    fn test_json_parameters_match_contract_types() {
+        let schema = schema();
+        let admin: AccountAddress = "36J5gb5QVYBvbda4cZkagN4LvVCXejyX8ScuEx8xyAQckVjBMA"
+            .parse()
+            .expect("address");
+
+        let init = schema
+            .init_param(&json!({
+                "token_address": { "index": 10300, "subindex": 0 },
+                "weight": 8500,
+                "decimals": 6,
+                "admin": { "Account": [admin.to_string()] },
+                "smart_wallets": [{ "index": 9833, "subindex": 0 }],
+                "limits": {
+                    "min_stake": 1000,
+                    "dust_threshold": 1000,
+                    "max_per_staker": { "Some": [50000] },
+                    "pool_capacity": { "None": [] }
+                }
+            }))
+            .expect("init param");
+        let expected = InitParam {
+            token_address: ContractAddress::new(10300, 0),
+            weight: 8500,
+            decimals: 6,
+            admin: Address::Account(admin),
+            smart_wallets: vec![ContractAddress::new(9833, 0)],
+            limits: StakeLimits {
+                min_stake: 1000,
+                dust_threshold: 1000,
+                max_per_staker: Some(50000),
+                pool_capacity: None,
+            },
+        };
+        assert_eq!(init.as_ref(), to_bytes(&expected).as_slice());
+
+        let unstake = schema
+            .receive_param(
+                "unstake",
+                Some(&json!({
+                    "amount": "250",
+                    "position": 3,
+                    "payout": { "None": [] },
+                    "signature": { "None": [] }
+                })),
+            )
+            .expect("unstake param");
+        let expected = UnstakeParam {
+            amount: 250.into(),
+            position: 3,
+            payout: None,
+            signature: None,
+        };
+        assert_eq!(unstake.as_ref(), to_bytes(&expected).as_slice());
+
+        assert_eq!(
+            schema
+                .receive_param("set_paused", None)
+                .expect("no param")
+                .as_ref(),
+            &[] as &[u8]
+        );
+        assert!(schema.receive_param("unstake", None).is_err());
+        assert!(schema.receive_param("no_such_entrypoint", None).is_err());
+    }
+
+    #[test]
+    fn test_return_values_and_errors_decode_to_json() {
+        let schema = schema();
+        let rewards = RewardResult {
+            days: 2,
+            rewards: 40,
+            amount_staked: 1000,
+        };
+        assert_eq!(
+            schema
+                .return_value("calculate_rewards", &to_bytes(&rewards))
+                .expect("return value"),
+            json!({ "days": 2, "rewards": 40, "amount_staked": 1000 })
+        );
+        assert_eq!(
+            schema.reject_reason("change_weight", &to_bytes(&StakingError::SenderIsNotAdmin)),
+            Some(json!({ "SenderIsNotAdmin": [] }))
+        );
+    }
+}
@@ -1,6 +1,9 @@
 use concordium_cis2::*;
 use concordium_std::*;
 use core::fmt::Debug;
+#[cfg(feature = "serde")]
+use serde::{Deserialize as SerdeDeserialize, Serialize as SerdeSerialize};
+pub mod mirror;
 pub mod types;
 use types::*;
 // ======== Type Definitions ========
@@ -8,34 +11,105 @@ use types::*;
 #[derive(Serial, DeserialWithState)]
 #[concordium(state_parameter = "S")]
 pub struct State<S = StateApi> {
-    pub stake_entries: StateMap<PublicKeyEd25519, StakeEntry, S>,
+    pub stake_entries: StateMap<PositionId, StakeEntry, S>,
+    pub next_position: PositionId,
     pub decimals: u8,
     pub token_address: ContractAddress,
+    /// The daily reward rate, as a percentage scaled by the token decimals.
+    /// Rewards accrue per second at the annual rate of `weight * 365`.
     pub weight: u32,
     pub paused: bool,
+    pub emergency: bool,
     pub admin: Address,
-    pub smart_wallet: ContractAddress,
+    pub smart_wallets: StateSet<ContractAddress, S>,
     pub reward_volume: u64,
+    pub payout_allowlist_enabled: bool,
+    pub payout_allowlist: StateSet<Address, S>,
+    pub nonces: StateMap<PublicKeyEd25519, u64, S>,
+    pub liquid_pool: LiquidPool,
+    pub receipt_balances: StateMap<Address, TokenAmountU64, S>,
+    /// Pairs of (owner, operator) for the receipt token.
+    pub operators: StateSet<(Address, Address), S>,
+    /// Pairs of (staker, operator) allowed to manage positions of the staker.
+    pub staker_operators: StateSet<(Staker, Address), S>,
+    pub metadata_url: MetadataUrl,
+    pub keeper_bounty: KeeperBounty,
+    pub epochs: Option<Epochs>,
+    /// The reward per stake at the start of epochs positions count from.
+    pub epoch_checkpoints: StateMap<u64, u128, S>,
+    /// Rewards by `weight` accrue in whole steps of this duration.
+    pub accrual_granularity: Duration,
+    pub limits: StakeLimits,
+    /// The principal staked in positions.
+    pub total_staked: u64,
+    /// The principal of each staker in positions and the liquid pool.
+    pub staker_totals: StateMap<Staker, u64, S>,
+    pub penalty_schedule: Option<PenaltySchedule>,
+    /// The protocol fee on rewards, in basis points.
+    pub protocol_fee_bps: u16,
+    pub fees_collected: u64,
+    pub fee_collector: Address,
+    /// The share of rewards paid to referrers, in basis points.
+    pub referral_bps: u16,
+    pub referral_stats: StateMap<Staker, ReferralStats, S>,
+    /// Restricts staking to the staker allowlist while enabled.
+    pub staker_gating: bool,
+    pub staker_allowlist: StateSet<Staker, S>,
+    pub staker_denylist: StateSet<Staker, S>,
 }
 
 impl State {
-    fn empty(
-        state_builder: &mut StateBuilder,
-        token_address: ContractAddress,
-        weight: u32,
-        decimals: u8,
-        admin: Address,
-        smart_wallet: ContractAddress,
-    ) -> Self {
+    fn empty(state_builder: &mut StateBuilder, param: InitParam, now: Timestamp) -> Self {
+        let mut trusted_wallets = state_builder.new_set();
+        for smart_wallet in param.smart_wallets {
+            trusted_wallets.insert(smart_wallet);
+        }
         State {
             stake_entries: state_builder.new_map(),
-            decimals,
-            token_address,
-            weight,
+            next_position: 1,
+            decimals: param.decimals,
+            token_address: param.token_address,
+            weight: param.weight,
             paused: false,
-            admin,
-            smart_wallet,
+            emergency: false,
+            admin: param.admin,
+            smart_wallets: trusted_wallets,
             reward_volume: 0,
+            payout_allowlist_enabled: false,
+            payout_allowlist: state_builder.new_set(),
+            nonces: state_builder.new_map(),
+            liquid_pool: LiquidPool {
+                total_assets: 0,
+                total_shares: 0,
+                last_accrual: now,
+            },
+            receipt_balances: state_builder.new_map(),
+            operators: state_builder.new_set(),
+            staker_operators: state_builder.new_set(),
+            metadata_url: MetadataUrl {
+                url: String::new(),
+                hash: None,
+            },
+            keeper_bounty: KeeperBounty {
+                per_item: 0,
+                max_per_call: 0,
+                max_share_bps: 0,
+            },
+            epochs: None,
+            epoch_checkpoints: state_builder.new_map(),
+            accrual_granularity: Duration::from_seconds(1),
+            limits: param.limits,
+            total_staked: 0,
+            staker_totals: state_builder.new_map(),
+            penalty_schedule: None,
+            protocol_fee_bps: 0,
+            fees_collected: 0,
+            fee_collector: param.admin,
+            referral_bps: 0,
+            referral_stats: state_builder.new_map(),
+            staker_gating: false,
+            staker_allowlist: state_builder.new_set(),
+            staker_denylist: state_builder.new_set(),
         }
     }
 
@@ -43,27 +117,693 @@ impl State {
         self.paused = paused;
     }
 
-    fn change_weight(&mut self, weight: u32) {
+    fn set_emergency(&mut self, emergency: bool) {
+        self.emergency = emergency;
+    }
+
+    fn set_payout_allowlist_enabled(&mut self, enabled: bool) {
+        self.payout_allowlist_enabled = enabled;
+    }
+
+    fn update_payout_allowlist(
+        &mut self,
+        params: UpdatePayoutAllowlistParams,
+        logger: &mut Logger,
+    ) -> ReceiveResult<()> {
+        for address in params.add {
+            self.payout_allowlist.insert(address);
+            logger.log(&StakingEvent::PayoutAllowlistUpdated {
+                address,
+                allowed: true,
+            })?;
+        }
+        for address in params.remove {
+            self.payout_allowlist.remove(&address);
+            logger.log(&StakingEvent::PayoutAllowlistUpdated {
+                address,
+                allowed: false,
+            })?;
+        }
+        Ok(())
+    }
+
+    fn update_staker_list(
+        &mut self,
+        params: UpdateStakerListParams,
+        logger: &mut Logger,
+    ) -> ReceiveResult<()> {
+        let list = match params.list {
+            StakerList::Allowlist => &mut self.staker_allowlist,
+            StakerList::Denylist => &mut self.staker_denylist,
+        };
+        for staker in params.add {
+            list.insert(staker);
+            logger.log(&StakingEvent::StakerListUpdated {
+                list: params.list,
+                staker,
+                listed: true,
+            })?;
+        }
+        for staker in params.remove {
+            list.remove(&staker);
+            logger.log(&StakingEvent::StakerListUpdated {
+                list: params.list,
+                staker,
+                listed: false,
+            })?;
+        }
+        Ok(())
+    }
+
+    fn staker_access(&self, staker: &Staker) -> StakerAccess {
+        let allowlisted = self.staker_allowlist.contains(staker);
+        let denylisted = self.staker_denylist.contains(staker);
+        StakerAccess {
+            allowlisted,
+            denylisted,
+            can_stake: !denylisted && (allowlisted || !self.staker_gating),
+        }
+    }
+
+    /// Checks that `staker` may open new stakes. Gating never keeps stakers
+    /// from unstaking what they already have.
+    fn ensure_may_stake(&self, staker: &Staker) -> Result<(), StakingError> {
+        ensure!(
+            !self.staker_denylist.contains(staker),
+            StakingError::StakerDenylisted
+        );
+        ensure!(
+            !self.staker_gating || self.staker_allowlist.contains(staker),
+            StakingError::NotAllowlisted
+        );
+        Ok(())
+    }
+
+    /// Consumes the next nonce of `key`, failing if `nonce` is not it.
+    fn use_nonce(&mut self, key: PublicKeyEd25519, nonce: u64) -> Result<(), StakingError> {
+        let mut entry = self.nonces.entry(key).or_insert(0);
+        ensure_eq!(*entry, nonce, StakingError::NonceMismatch);
+        *entry += 1;
+        Ok(())
+    }
+
+    fn update_smart_wallets(
+        &mut self,
+        params: UpdateSmartWalletsParams,
+        logger: &mut Logger,
+    ) -> ReceiveResult<()> {
+        for smart_wallet in params.add {
+            self.smart_wallets.insert(smart_wallet);
+            logger.log(&StakingEvent::SmartWalletUpdated {
+                smart_wallet,
+                trusted: true,
+            })?;
+        }
+        for smart_wallet in params.remove {
+            self.smart_wallets.remove(&smart_wallet);
+            logger.log(&StakingEvent::SmartWalletUpdated {
+                smart_wallet,
+                trusted: false,
+            })?;
+        }
+        Ok(())
+    }
+
+    /// Changes the reward weight at `now`. The liquid pool first accrues what
+    /// it earned at the old weight.
+    fn change_weight(&mut self, weight: u32, now: Timestamp) {
+        self.accrue_liquid_rewards(now);
         self.weight = weight;
     }
+
     fn deduct_volume(&mut self, amount: u64) {
-        let volume = self.reward_volume.checked_sub(amount).unwrap_or_else(|| 0);
+        let volume = self.reward_volume.saturating_sub(amount);
         self.reward_volume = volume;
     }
+
+    /// Takes up to `rewards` out of the reward volume and returns the amount
+    /// that could actually be paid. The remainder is left for the caller to
+    /// record as pending.
+    fn take_rewards(&mut self, rewards: u64) -> u64 {
+        let paid = rewards.min(self.reward_volume);
+        self.deduct_volume(paid);
+        paid
+    }
+
+    /// Rolls the epochs forward to `now`, recording the reward per stake at
+    /// the start of the epoch pending stake became eligible in.
+    fn roll_epochs(&mut self, now: Timestamp) {
+        if let Some(epochs) = &mut self.epochs {
+            if let Some((epoch, reward_per_stake)) = epochs.roll(now) {
+                self.epoch_checkpoints
+                    .entry(epoch)
+                    .or_insert(reward_per_stake);
+            }
+        }
+    }
+
+    /// Records the reward per stake at the start of the current epoch, so
+    /// positions can count their rewards from it. Returns the current epoch.
+    fn checkpoint_epoch(&mut self) -> Option<u64> {
+        let epochs = self.epochs?;
+        self.epoch_checkpoints
+            .entry(epochs.current)
+            .or_insert(epochs.reward_per_stake);
+        Some(epochs.current)
+    }
+
+    /// The principal `staker` has staked across its positions and in the
+    /// liquid pool.
+    fn staker_total(&self, staker: &Staker) -> u64 {
+        self.staker_totals.get(staker).map_or(0, |total| *total)
+    }
+
+    /// The principal staked in positions and the liquid pool together.
+    fn pool_total(&self) -> u64 {
+        self.total_staked + self.liquid_pool.total_assets
+    }
+
+    /// Ensures the pool has room for `amount` more.
+    fn ensure_capacity(&self, amount: u64) -> Result<(), StakingError> {
+        if let Some(capacity) = self.limits.pool_capacity {
+            ensure!(
+                self.pool_total() + amount <= capacity,
+                StakingError::PoolCapacityExceeded
+            );
+        }
+        Ok(())
+    }
+
+    /// Records a new stake of `amount` by `staker` within the stake limits.
+    fn add_stake(&mut self, staker: &Staker, amount: u64) -> Result<(), StakingError> {
+        self.ensure_stake_limits(staker, amount)?;
+        self.track_stake(staker, amount);
+        Ok(())
+    }
+
+    /// Ensures a new stake of `amount` by `staker` is within the stake limits.
+    fn ensure_stake_limits(&self, staker: &Staker, amount: u64) -> Result<(), StakingError> {
+        ensure!(
+            amount >= self.limits.min_stake,
+            StakingError::CannotStakeLessThanAllowAmount
+        );
+        if let Some(max) = self.limits.max_per_staker {
+            ensure!(
+                self.staker_total(staker) + amount <= max,
+                StakingError::StakeAboveStakerMaximum
+            );
+        }
+        self.ensure_capacity(amount)
+    }
+
+    /// Adds `amount` to the principal of `staker` without checking limits,
+    /// for rewards compounded into it and positions handed over to it.
+    fn track_stake(&mut self, staker: &Staker, amount: u64) {
+        self.total_staked += amount;
+        *self.staker_totals.entry(*staker).or_insert(0) += amount;
+    }
+
+    /// Takes `amount` out of the principal counted for `staker`.
+    fn untrack_stake(&mut self, staker: &Staker, amount: u64) {
+        let remaining = self.staker_totals.get_mut(staker).map(|mut total| {
+            *total = total.saturating_sub(amount);
+            *total
+        });
+        if remaining == Some(0) {
+            self.staker_totals.remove(staker);
+        }
+    }
+
+    /// Takes `amount` out of the principal of `staker`, and out of the stake of
+    /// the epoch subsystem for a position counting rewards from `reward_epoch`.
+    fn remove_stake(&mut self, staker: &Staker, reward_epoch: Option<u64>, amount: u64) {
+        self.total_staked = self.total_staked.saturating_sub(amount);
+        self.untrack_stake(staker, amount);
+        if let (Some(epochs), Some(reward_epoch)) = (&mut self.epochs, reward_epoch) {
+            if reward_epoch > epochs.current {
+                epochs.pending_stake = epochs.pending_stake.saturating_sub(amount);
+            } else {
+                epochs.eligible_stake = epochs.eligible_stake.saturating_sub(amount);
+            }
+        }
+    }
+
+    /// The rewards `amount` of a position has earned by `now`.
+    fn rewards_due(
+        &self,
+        amount: u64,
+        time_of_stake: Timestamp,
+        reward_epoch: Option<u64>,
+        now: Timestamp,
+    ) -> u64 {
+        match reward_epoch {
+            Some(reward_epoch) => self.epoch_rewards(amount, reward_epoch, now),
+            None => accrued_rewards(
+                amount,
+                self.weight,
+                self.decimals,
+                self.accrued_millis(time_of_stake, now),
+            ),
+        }
+    }
+
+    /// Applies an admin update of the settings at `now`.
+    fn update_setting(
+        &mut self,
+        update: SettingUpdate,
+        now: Timestamp,
+    ) -> Result<(), StakingError> {
+        match update {
+            SettingUpdate::Emergency(emergency) => self.set_emergency(emergency),
+            SettingUpdate::PayoutAllowlistEnabled(enabled) => {
+                self.set_payout_allowlist_enabled(enabled)
+            }
+            SettingUpdate::KeeperBounty(keeper_bounty) => {
+                ensure!(
+                    keeper_bounty.max_share_bps <= 10_000,
+                    StakingError::InvalidKeeperBounty
+                );
+                self.keeper_bounty = keeper_bounty;
+            }
+            SettingUpdate::Epochs(config) => self.configure_epochs(config, now)?,
+            SettingUpdate::AccrualGranularity(granularity) => {
+                ensure!(
+                    granularity.millis() > 0,
+                    StakingError::InvalidAccrualGranularity
+                );
+                // the liquid pool keeps what it earned at the old granularity
+                self.accrue_liquid_rewards(now);
+                self.accrual_granularity = granularity;
+            }
+            SettingUpdate::StakeLimits(limits) => {
+                ensure!(limits.is_valid(), StakingError::InvalidStakeLimits);
+                self.limits = limits;
+            }
+            SettingUpdate::PenaltySchedule(schedule) => {
+                if let Some(schedule) = &schedule {
+                    ensure!(
+                        schedule.max_bps <= 10_000 && schedule.duration.millis() > 0,
+                        StakingError::InvalidPenaltySchedule
+                    );
+                }
+                self.penalty_schedule = schedule;
+            }
+            SettingUpdate::ProtocolFee(fee_bps) => {
+                ensure!(fee_bps <= 10_000, StakingError::InvalidFee);
+                self.protocol_fee_bps = fee_bps;
+            }
+            SettingUpdate::FeeCollector(fee_collector) => self.fee_collector = fee_collector,
+            SettingUpdate::ReferralRate(referral_bps) => {
+                ensure!(referral_bps <= 10_000, StakingError::InvalidReferralRate);
+                self.referral_bps = referral_bps;
+            }
+            SettingUpdate::StakerGating(enabled) => self.staker_gating = enabled,
+            SettingUpdate::Admin(admin) => self.admin = admin,
+        }
+        Ok(())
+    }
+
+    /// Sets the epoch length and budget. The first time starts epoch 0 and
+    /// from then on new positions earn from the epoch budgets; later changes
+    /// take effect from the current epoch.
+    fn configure_epochs(
+        &mut self,
+        config: EpochConfig,
+        now: Timestamp,
+    ) -> Result<(), StakingError> {
+        ensure!(config.length.millis() > 0, StakingError::InvalidEpochLength);
+        self.roll_epochs(now);
+        match &mut self.epochs {
+            Some(epochs) => {
+                epochs.length = config.length;
+                epochs.budget = config.budget;
+            }
+            None => {
+                self.epochs = Some(Epochs {
+                    length: config.length,
+                    budget: config.budget,
+                    current: 0,
+                    current_start: now,
+                    eligible_stake: 0,
+                    pending_stake: 0,
+                    reward_per_stake: 0,
+                })
+            }
+        }
+        Ok(())
+    }
+
+    /// Credits the referrer of a position its share of `rewards` paid on the
+    /// position, as far as the reward volume allows. Returns the referrer and
+    /// the amount credited.
+    fn credit_referrer(&mut self, referrer: Option<Staker>, rewards: u64) -> Option<(Staker, u64)> {
+        let referrer = referrer?;
+        let share = (u128::from(rewards) * u128::from(self.referral_bps) / 10_000) as u64;
+        let credited = self.take_rewards(share);
+        if credited == 0 {
+            return None;
+        }
+        let mut stats = self.referral_stats.entry(referrer).or_default();
+        stats.earned += credited;
+        stats.claimable += credited;
+        Some((referrer, credited))
+    }
+
+    /// Takes the protocol fee out of `rewards` being paid out and returns it.
+    fn take_protocol_fee(&mut self, rewards: u64) -> u64 {
+        let fee = self.protocol_fee(rewards);
+        self.fees_collected += fee;
+        fee
+    }
+
+    /// The protocol fee on paying out `rewards`.
+    fn protocol_fee(&self, rewards: u64) -> u64 {
+        (u128::from(rewards) * u128::from(self.protocol_fee_bps) / 10_000) as u64
+    }
+
+    /// The early exit penalty on unstaking `principal` with `rewards` due from
+    /// a position staked at `time_of_stake`, as the part taken from the
+    /// principal and the part taken from the rewards.
+    fn early_exit_penalty(
+        &self,
+        time_of_stake: Timestamp,
+        now: Timestamp,
+        principal: u64,
+        rewards: u64,
+    ) -> (u64, u64) {
+        let Some(schedule) = &self.penalty_schedule else {
+            return (0, 0);
+        };
+        let duration = schedule.duration.millis();
+        let remaining = duration.saturating_sub(
+            now.duration_since(time_of_stake)
+                .map_or(0, |elapsed| elapsed.millis()),
+        );
+        let penalty = |base: u64| {
+            (u128::from(base) * u128::from(schedule.max_bps) * u128::from(remaining)
+                / (10_000 * u128::from(duration))) as u64
+        };
+        match schedule.base {
+            PenaltyBase::Principal => (penalty(principal), 0),
+            PenaltyBase::Rewards => (0, penalty(rewards)),
+        }
+    }
+
+    /// The time between `since` and `now` rewards accrue for, in whole steps
+    /// of the accrual granularity.
+    fn accrued_millis(&self, since: Timestamp, now: Timestamp) -> u64 {
+        let granularity = self.accrual_granularity.millis();
+        now.duration_since(since)
+            .map_or(0, |duration| duration.millis() / granularity * granularity)
+    }
+
+    /// The share of the epoch budgets `amount` has earned from the start of
+    /// `reward_epoch` up to the epoch `now` falls in.
+    fn epoch_rewards(&self, amount: u64, reward_epoch: u64, now: Timestamp) -> u64 {
+        let Some(mut epochs) = self.epochs else {
+            return 0;
+        };
+        let checkpoint = epochs.roll(now);
+        if reward_epoch >= epochs.current {
+            return 0;
+        }
+        let start = match checkpoint {
+            Some((epoch, reward_per_stake)) if epoch == reward_epoch => reward_per_stake,
+            _ => self
+                .epoch_checkpoints
+                .get(&reward_epoch)
+                .map_or(epochs.reward_per_stake, |reward_per_stake| {
+                    *reward_per_stake
+                }),
+        };
+        ((epochs.reward_per_stake - start) * u128::from(amount) / REWARD_SCALE) as u64
+    }
+
+    fn receipt_balance(&self, owner: &Address) -> TokenAmountU64 {
+        self.receipt_balances
+            .get(owner)
+            .map_or(TokenAmountU64(0), |balance| *balance)
+    }
+
+    fn mint_receipts(&mut self, owner: Address, amount: TokenAmountU64) {
+        *self
+            .receipt_balances
+            .entry(owner)
+            .or_insert(TokenAmountU64(0)) += amount;
+    }
+
+    fn burn_receipts(
+        &mut self,
+        owner: &Address,
+        amount: TokenAmountU64,
+    ) -> Result<(), StakingError> {
+        let mut balance = self
+            .receipt_balances
+            .entry(*owner)
+            .occupied_or(StakingError::InsufficientFunds)?;
+        ensure!(*balance >= amount, StakingError::InsufficientFunds);
+        *balance -= amount;
+        Ok(())
+    }
+
+    /// Moves receipt tokens to `to`. Receiving them is staking in the liquid
+    /// pool, so the receiver has to be allowed to stake, unless it is this
+    /// contract at `self_address` redeeming them.
+    fn transfer_receipts(
+        &mut self,
+        self_address: ContractAddress,
+        token_id: &ReceiptTokenId,
+        amount: TokenAmountU64,
+        from: &Address,
+        to: &Receiver,
+        data: &AdditionalData,
+    ) -> Result<(), StakingError> {
+        ensure_eq!(*token_id, RECEIPT_TOKEN_ID, StakingError::InvalidTokenId);
+        if amount.0 == 0 {
+            return Ok(());
+        }
+        if to.address() != Address::Contract(self_address) {
+            let (staker, _) = self.receiving_staker(to, data);
+            self.ensure_may_stake(&staker)?;
+        }
+        self.burn_receipts(from, amount)?;
+        self.mint_receipts(to.address(), amount);
+        Ok(())
+    }
+
+    /// The staker a token sent to `to` is held for: the public key given as
+    /// data to a trusted smart wallet, or else the receiving address.
+    fn receiving_staker(
+        &self,
+        to: &Receiver,
+        data: &AdditionalData,
+    ) -> (Staker, Option<ContractAddress>) {
+        match (to, from_bytes::<PublicKeyEd25519>(data.as_ref())) {
+            (Receiver::Contract(wallet, _), Ok(key)) if self.smart_wallets.contains(wallet) => {
+                (Staker::PublicKey(key), Some(*wallet))
+            }
+            _ => (Staker::Address(to.address()), None),
+        }
+    }
+
+    /// Moves a position to the holder of `to`. A position sent to a trusted
+    /// smart wallet with a public key as data becomes owned by that key, which
+    /// has to be allowed to stake.
+    fn transfer_position(
+        &mut self,
+        position: PositionId,
+        amount: TokenAmountU64,
+        from: &Address,
+        to: &Receiver,
+        data: &AdditionalData,
+    ) -> Result<Option<Staker>, StakingError> {
+        let (owner, smart_wallet) = self.receiving_staker(to, data);
+        let (previous_owner, principal) = {
+            let entry = self
+                .stake_entries
+                .get(&position)
+                .ok_or(StakingError::InvalidTokenId)?;
+            if amount.0 == 0 {
+                return Ok(None);
+            }
+            ensure!(
+                amount.0 == 1 && position_holder(&entry)? == *from,
+                StakingError::InsufficientFunds
+            );
+            (entry.owner, entry.amount.0)
+        };
+        self.ensure_may_stake(&owner)?;
+        self.stake_entries.entry(position).and_modify(|entry| {
+            entry.owner = owner;
+            entry.smart_wallet = smart_wallet;
+        });
+        // the principal moves with the position; transfers are not capped
+        self.remove_stake(&previous_owner, None, principal);
+        self.track_stake(&owner, principal);
+        Ok(Some(owner))
+    }
+
+    /// The balance of `address` of a receipt or position token.
+    fn token_balance(
+        &self,
+        token_id: &ReceiptTokenId,
+        address: &Address,
+    ) -> Result<TokenAmountU64, StakingError> {
+        match token_position(token_id) {
+            Some(position) => {
+                let entry = self
+                    .stake_entries
+                    .get(&position)
+                    .ok_or(StakingError::InvalidTokenId)?;
+                let held = position_holder(&entry)? == *address;
+                Ok(TokenAmountU64(held.into()))
+            }
+            None => {
+                ensure_eq!(*token_id, RECEIPT_TOKEN_ID, StakingError::InvalidTokenId);
+                Ok(self.receipt_balance(address))
+            }
+        }
+    }
+
+    fn update_operator(&mut self, update: OperatorUpdate, owner: Address, operator: Address) {
+        match update {
+            OperatorUpdate::Add => self.operators.insert((owner, operator)),
+            OperatorUpdate::Remove => self.operators.remove(&(owner, operator)),
+        };
+    }
+
+    fn update_staker_operator(&mut self, update: OperatorUpdate, owner: Staker, operator: Address) {
+        match update {
+            OperatorUpdate::Add => self.staker_operators.insert((owner, operator)),
+            OperatorUpdate::Remove => self.staker_operators.remove(&(owner, operator)),
+        };
+    }
+
+    fn is_staker_operator(&self, owner: &Staker, address: &Address) -> bool {
+        self.staker_operators.contains(&(*owner, *address))
+    }
+
+    /// Rolls the rewards a position has accrued into its principal if it has
+    /// auto compounding enabled. Rewards the pool cannot pay are left pending.
+    /// Returns the owner, the rewards added, the rewards left pending and the
+    /// new principal.
+    fn compound_position(
+        &mut self,
+        position: PositionId,
+        now: Timestamp,
+    ) -> Option<(Staker, u64, u64, TokenAmountU64)> {
+        let (owner, amount, time_of_stake, reward_epoch) = {
+            let entry = self.stake_entries.get(&position)?;
+            if !entry.auto_compound {
+                return None;
+            }
+            (
+                entry.owner,
+                entry.amount,
+                entry.time_of_stake,
+                entry.reward_epoch,
+            )
+        };
+        let rewards = self.rewards_due(amount.0, time_of_stake, reward_epoch, now);
+        if rewards == 0 {
+            return None;
+        }
+        let paid = self.take_rewards(rewards);
+        let accrued = Duration::from_millis(self.accrued_millis(time_of_stake, now));
+        let reward_epoch = match reward_epoch {
+            Some(_) => {
+                // the compounded rewards earn from the current epoch on
+                if let Some(epochs) = &mut self.epochs {
+                    epochs.eligible_stake += paid;
+                }
+                self.checkpoint_epoch()
+            }
+            None => None,
+        };
+        self.track_stake(&owner, paid);
+
+        let mut entry = self.stake_entries.get_mut(&position)?;
+        entry.amount += TokenAmountU64(paid);
+        entry.pending_rewards += rewards - paid;
+        if reward_epoch.is_some() {
+            entry.reward_epoch = reward_epoch;
+        } else {
+            entry.time_of_stake = time_of_stake.checked_add(accrued).unwrap_or(now);
+        }
+        Some((owner, paid, rewards - paid, entry.amount))
+    }
+
+    /// The rewards the liquid pool has earned since its last accrual, capped
+    /// at what the reward volume can pay, and the milliseconds they cover.
+    fn liquid_rewards_due(&self, now: Timestamp) -> (u64, u64) {
+        let millis = self.accrued_millis(self.liquid_pool.last_accrual, now);
+        let rewards = accrued_rewards(
+            self.liquid_pool.total_assets,
+            self.weight,
+            self.decimals,
+            millis,
+        );
+        (rewards.min(self.reward_volume), millis)
+    }
+
+    /// Moves the rewards earned by the liquid pool into it, which raises the
+    /// value of every receipt token.
+    fn accrue_liquid_rewards(&mut self, now: Timestamp) {
+        let (rewards, millis) = self.liquid_rewards_due(now);
+        if millis == 0 {
+            return;
+        }
+        let paid = self.take_rewards(rewards);
+        let pool = &mut self.liquid_pool;
+        pool.total_assets += paid;
+        pool.last_accrual = pool
+            .last_accrual
+            .checked_add(Duration::from_millis(millis))
+            .unwrap_or(now);
+    }
+
+    /// Adds `amount` to the liquid pool and returns the receipt tokens it buys.
+    fn deposit_liquid(&mut self, amount: u64) -> u64 {
+        let pool = &mut self.liquid_pool;
+        let shares = if pool.total_shares == 0 || pool.total_assets == 0 {
+            amount
+        } else {
+            mul_div(amount, pool.total_shares, pool.total_assets)
+        };
+        pool.total_assets += amount;
+        pool.total_shares += shares;
+        shares
+    }
+
+    /// Takes `shares` receipt tokens out of the liquid pool and returns the
+    /// staked tokens they are worth.
+    fn withdraw_liquid(&mut self, shares: u64) -> u64 {
+        let pool = &mut self.liquid_pool;
+        let amount = mul_div(shares, pool.total_assets, pool.total_shares);
+        pool.total_assets -= amount;
+        pool.total_shares -= shares;
+        amount
+    }
 }
 
 // ======== Contract Implementation ========
 
-#[init(contract = "gona_stake", parameter = "InitParam")]
+#[init(
+    contract = "gona_stake",
+    parameter = "InitParam",
+    error = "StakingError",
+    event = "StakingEvent"
+)]
 fn contract_init(ctx: &InitContext, state_builder: &mut StateBuilder) -> InitResult<State> {
     let param: InitParam = ctx.parameter_cursor().get()?;
+    ensure!(
+        param.limits.is_valid(),
+        StakingError::InvalidStakeLimits.into()
+    );
     Ok(State::empty(
         state_builder,
-        param.token_address,
-        param.weight,
-        param.decimals,
-        param.admin,
-        param.smart_wallet,
+        param,
+        ctx.metadata().slot_time(),
     ))
 }
 
@@ -71,71 +811,93 @@ fn contract_init(ctx: &InitContext, state_builder: &mut StateBuilder) -> InitRes
     contract = "gona_stake",
     name = "stake",
     error = "StakingError",
-    parameter = "OnReceivingCis2DataParams<ContractTokenId,ContractTokenAmount,PublicKeyEd25519>",
+    parameter = "OnReceivingCis2Params<ContractTokenId,ContractTokenAmount>",
     enable_logger,
     mutable
 )]
 fn stake(ctx: &ReceiveContext, host: &mut Host<State>, logger: &mut Logger) -> ReceiveResult<()> {
-    let parameter: OnReceivingCis2DataParams<
-        ContractTokenId,
-        ContractTokenAmount,
-        PublicKeyEd25519,
-    > = ctx.parameter_cursor().get()?;
+    let parameter: OnReceivingCis2Params<ContractTokenId, ContractTokenAmount> =
+        ctx.parameter_cursor().get()?;
 
     let amount = parameter.amount;
     let token_id = parameter.token_id;
     let state = host.state_mut();
     let gona_token = state.token_address;
 
-    let staker = parameter.data;
+    let (staker, referrer) = staker_from_transfer(parameter.from, &parameter.data)?;
+    ensure!(referrer != Some(staker), StakingError::SelfReferral.into());
+    // a contract could never be paid its referral rewards, as it has to name
+    // the entrypoint it is paid to
+    ensure!(
+        !matches!(referrer, Some(Staker::Address(Address::Contract(_)))),
+        StakingError::InvalidReferrer.into()
+    );
+    state.ensure_may_stake(&staker)?;
+    let smart_wallet = staker_smart_wallet(state, &staker, parameter.from)?;
 
     // Ensures that only contracts can call this hook function.
     let sender_contract_address = match ctx.sender() {
         Address::Contract(sender_contract_address) => sender_contract_address,
         Address::Account(_) => bail!(StakingError::OnlyContractCanStake.into()),
     };
-    // Cannot stake less than 0.001 of our token
-    ensure!(
-        amount.0.ge(&1000),
-        StakingError::CannotStakeLessThanAllowAmount.into()
-    );
     ensure_eq!(
         sender_contract_address,
         gona_token,
         StakingError::SenderContractAddressIsNotAllowedToStake.into()
     );
-    let mut entry = StakeEntry {
+    state.add_stake(&staker, amount.0)?;
+    let now = ctx.metadata().slot_time();
+    state.roll_epochs(now);
+    // with epochs configured, the stake is eligible from the next epoch on
+    let reward_epoch = state.epochs.as_mut().map(|epochs| {
+        epochs.pending_stake += amount.0;
+        epochs.current + 1
+    });
+    let position = state.next_position;
+    state.next_position += 1;
+    state.stake_entries.entry(position).or_insert(StakeEntry {
+        owner: staker,
         amount,
-        time_of_stake: ctx.metadata().slot_time(),
+        time_of_stake: now,
         token_id,
-    };
-    if let Some(stake_entry) = state.stake_entries.remove_and_get(&staker) {
-        let days_of_stake = ctx
-            .metadata()
-            .slot_time()
-            .duration_since(stake_entry.time_of_stake)
-            .ok_or(StakingError::DaysOfStakeCouldNotBeCalculated)?
-            .days();
-        let previous_amount = stake_entry.amount;
-        let rewards = calculate_percent(previous_amount.0, state.weight, state.decimals);
-        if days_of_stake > 0 {
-            let rewards = rewards * days_of_stake;
-            let new_amount = previous_amount + TokenAmountU64(rewards);
-            entry.amount = new_amount;
-        } else {
-            entry.amount += previous_amount;
-        }
-        stake_entry.delete();
-        state.stake_entries.entry(staker).or_insert(entry);
-    } else {
-        state.stake_entries.entry(staker).or_insert(entry);
+        pending_rewards: 0,
+        smart_wallet,
+        auto_compound: false,
+        reward_epoch,
+        referrer,
+    });
+    if let Some(referrer) = referrer {
+        state.referral_stats.entry(referrer).or_default().referees += 1;
     }
+    let owner = smart_wallet.map_or(parameter.from, Address::Contract);
+
+    logger.log(&StakingEvent::Mint(MintEvent {
+        token_id: position_token_id(position),
+        amount: TokenAmountU64(1),
+        owner,
+    }))?;
     logger.log(&StakingEvent::Staked {
+        position,
         staker,
         amount,
-        time: ctx.metadata().slot_time(),
+        time: now,
     })?;
+    if let Some(referrer) = referrer {
+        logger.log(&StakingEvent::Referred {
+            position,
+            staker,
+            referrer,
+        })?;
+    }
 
+    deposit_to_smart_wallet(
+        host,
+        ctx.self_address(),
+        &staker,
+        smart_wallet,
+        position_token_id(position),
+        TokenAmountU64(1),
+    )?;
     Ok(())
 }
 
@@ -156,17 +918,27 @@ fn deposit_cis2_tokens(
         ctx.parameter_cursor().get()?;
 
     // Ensures that only contracts can call this hook function.
-    match ctx.sender() {
+    let sender_contract_address = match ctx.sender() {
         Address::Contract(sender_contract_address) => sender_contract_address,
         Address::Account(_) => bail!(StakingError::OnlyContractCanStake.into()),
     };
+    // Only the staked token can fund rewards, not receipt tokens sent here.
+    ensure_eq!(
+        sender_contract_address,
+        host.state().token_address,
+        StakingError::SenderContractAddressIsNotAllowedToStake.into()
+    );
 
-    host.state_mut().reward_volume += param.amount.0;
+    let state = host.state_mut();
+    state.reward_volume += param.amount.0;
 
     logger.log(&StakingEvent::TokenDeposit {
         sender: param.from,
         amount: param.amount,
     })?;
+    logger.log(&StakingEvent::RewardVolumeChanged {
+        reward_volume: TokenAmountU64(state.reward_volume),
+    })?;
 
     Ok(())
 }
@@ -175,173 +947,1838 @@ fn deposit_cis2_tokens(
 #[receive(
     contract = "gona_stake",
     name = "get_stake_info",
-    parameter = "PublicKeyEd25519",
+    parameter = "PositionId",
     return_value = "Option<StakeEntry>"
 )]
 fn get_stake_info(ctx: &ReceiveContext, host: &Host<State>) -> ReceiveResult<StakeQuery> {
-    let param: PublicKeyEd25519 = ctx.parameter_cursor().get()?;
+    let param: PositionId = ctx.parameter_cursor().get()?;
     let stake_entry_ref = host.state().stake_entries.get(&param);
     // Convert the StateRef to Option<StakeEntry>
     let stake_entry_option = stake_entry_ref.map(|entry_ref| entry_ref.to_owned());
     Ok(stake_entry_option)
 }
 
-#[receive(contract = "gona_stake", name = "set_paused", mutable)]
-fn set_paused(ctx: &ReceiveContext, host: &mut Host<State>) -> ReceiveResult<()> {
+#[receive(
+    contract = "gona_stake",
+    name = "set_paused",
+    error = "StakingError",
+    enable_logger,
+    mutable
+)]
+fn set_paused(
+    ctx: &ReceiveContext,
+    host: &mut Host<State>,
+    logger: &mut Logger,
+) -> ReceiveResult<()> {
     ensure_eq!(
         ctx.sender(),
         host.state.admin,
         StakingError::SenderIsNotAdmin.into()
     );
     host.state_mut().set_paused(true);
+    logger.log(&StakingEvent::PauseChanged { paused: true })?;
     Ok(())
 }
 
 #[receive(
     contract = "gona_stake",
-    name = "change_weight",
-    parameter = "u32",
+    name = "update_payout_allowlist",
+    parameter = "UpdatePayoutAllowlistParams",
+    error = "StakingError",
+    enable_logger,
     mutable
 )]
-fn change_weight(ctx: &ReceiveContext, host: &mut Host<State>) -> ReceiveResult<()> {
-    let weight: u32 = ctx.parameter_cursor().get()?;
+fn update_payout_allowlist(
+    ctx: &ReceiveContext,
+    host: &mut Host<State>,
+    logger: &mut Logger,
+) -> ReceiveResult<()> {
+    let params: UpdatePayoutAllowlistParams = ctx.parameter_cursor().get()?;
     ensure_eq!(
         ctx.sender(),
         host.state.admin,
         StakingError::SenderIsNotAdmin.into()
     );
-    host.state_mut().change_weight(weight);
-
-    Ok(())
+    host.state_mut().update_payout_allowlist(params, logger)
 }
 
+/// Adds stakers to or removes them from the staker allowlist or denylist.
 #[receive(
     contract = "gona_stake",
-    name = "view_reward_volume",
-    return_value = "u64"
+    name = "update_staker_list",
+    parameter = "UpdateStakerListParams",
+    error = "StakingError",
+    enable_logger,
+    mutable
 )]
-fn view_reward(_ctx: &ReceiveContext, host: &Host<State>) -> ReceiveResult<u64> {
-    Ok(host.state().reward_volume)
+fn update_staker_list(
+    ctx: &ReceiveContext,
+    host: &mut Host<State>,
+    logger: &mut Logger,
+) -> ReceiveResult<()> {
+    let params: UpdateStakerListParams = ctx.parameter_cursor().get()?;
+    ensure_eq!(
+        ctx.sender(),
+        host.state.admin,
+        StakingError::SenderIsNotAdmin.into()
+    );
+    host.state_mut().update_staker_list(params, logger)
 }
 
 #[receive(
     contract = "gona_stake",
-    name = "calculate_rewards",
-    parameter = "PublicKeyEd25519",
-    error = "StakingError"
+    name = "view_staker_access",
+    parameter = "Staker",
+    return_value = "StakerAccess"
 )]
-fn calculate_rewards(ctx: &ReceiveContext, host: &Host<State>) -> ReceiveResult<RewardResult> {
-    let staker: PublicKeyEd25519 = ctx.parameter_cursor().get()?;
-    if let Some(stake_entry) = host.state.stake_entries.get(&staker) {
-        let days = ctx
-            .metadata()
-            .slot_time()
-            .duration_since(stake_entry.time_of_stake)
-            .ok_or(StakingError::DaysOfStakeCouldNotBeCalculated)?
-            .days();
-        let previous_amount = stake_entry.amount;
-        let rewards = calculate_percent(previous_amount.0, host.state.weight, host.state.decimals);
-        let rewards = rewards * days;
-        Ok(RewardResult {
-            days,
-            rewards,
-            amount_staked: stake_entry.amount.0,
-        })
-    } else {
-        bail!(StakingError::StakingNotFound.into())
-    }
+fn view_staker_access(ctx: &ReceiveContext, host: &Host<State>) -> ReceiveResult<StakerAccess> {
+    let staker: Staker = ctx.parameter_cursor().get()?;
+    Ok(host.state().staker_access(&staker))
 }
 
-// Helper Functions
-
-/// calculate the reward of the stake for the current season.
-/// the calculate is done using the decimals of the token.
-/// this fn assumes that the amount and weight is `n * 10.pow(decimals)`
-fn calculate_percent(amount: u64, weight: u32, decimals: u8) -> u64 {
-    (amount * (weight as u64)) / (100 * (10_i32.pow(decimals as u32) as u64))
+/// Returns the hash a public key staker has to sign to unstake to a custom
+/// receiver.
+#[receive(
+    contract = "gona_stake",
+    name = "payout_message_hash",
+    parameter = "PayoutMessage",
+    return_value = "[u8;32]",
+    crypto_primitives
+)]
+fn payout_message_hash(
+    ctx: &ReceiveContext,
+    _host: &Host<State>,
+    crypto_primitives: &impl HasCryptoPrimitives,
+) -> ReceiveResult<[u8; 32]> {
+    let message: PayoutMessage = ctx.parameter_cursor().get()?;
+    Ok(crypto_primitives.hash_sha2_256(&to_bytes(&message)).0)
 }
 
 #[receive(
     contract = "gona_stake",
-    name = "unstake",
+    name = "update_smart_wallets",
+    parameter = "UpdateSmartWalletsParams",
     error = "StakingError",
-    parameter = "UnstakeParam",
     enable_logger,
     mutable
 )]
-fn unstake(ctx: &ReceiveContext, host: &mut Host<State>, logger: &mut Logger) -> ReceiveResult<()> {
-    let param: UnstakeParam = ctx.parameter_cursor().get()?;
-    let state = host.state_mut();
-
-    let weight = state.weight;
-    let decimals = state.decimals;
-    let reward_volume = state.reward_volume;
+fn update_smart_wallets(
+    ctx: &ReceiveContext,
+    host: &mut Host<State>,
+    logger: &mut Logger,
+) -> ReceiveResult<()> {
+    let params: UpdateSmartWalletsParams = ctx.parameter_cursor().get()?;
+    ensure_eq!(
+        ctx.sender(),
+        host.state.admin,
+        StakingError::SenderIsNotAdmin.into()
+    );
+    host.state_mut().update_smart_wallets(params, logger)
+}
 
-    let stake_entry = state
-        .stake_entries
-        .get(&param.staker)
-        .ok_or(StakingError::StakingNotFound)?;
+#[receive(
+    contract = "gona_stake",
+    name = "view_smart_wallets",
+    return_value = "Vec<ContractAddress>"
+)]
+fn view_smart_wallets(
+    _ctx: &ReceiveContext,
+    host: &Host<State>,
+) -> ReceiveResult<Vec<ContractAddress>> {
+    Ok(host
+        .state()
+        .smart_wallets
+        .iter()
+        .map(|wallet| *wallet)
+        .collect())
+}
 
-    let previous_amount = stake_entry.amount;
-    ensure!(
-        previous_amount.0.ge(&param.amount.0),
-        StakingError::InsufficientFunds.into()
+#[receive(
+    contract = "gona_stake",
+    name = "change_weight",
+    parameter = "u32",
+    error = "StakingError",
+    enable_logger,
+    mutable
+)]
+fn change_weight(
+    ctx: &ReceiveContext,
+    host: &mut Host<State>,
+    logger: &mut Logger,
+) -> ReceiveResult<()> {
+    let weight: u32 = ctx.parameter_cursor().get()?;
+    ensure_eq!(
+        ctx.sender(),
+        host.state.admin,
+        StakingError::SenderIsNotAdmin.into()
     );
-    let days_of_stake = ctx
-        .metadata()
-        .slot_time()
-        .duration_since(stake_entry.time_of_stake)
-        .ok_or(StakingError::DaysOfStakeCouldNotBeCalculated)?
-        .days();
+    let reward_volume = host.state.reward_volume;
+    host.state_mut()
+        .change_weight(weight, ctx.metadata().slot_time());
+    logger.log(&StakingEvent::WeightChanged { weight })?;
+    log_reward_volume(logger, host.state(), reward_volume)
+}
 
-    let mut amount = param.amount;
-    let token_address = state.token_address;
-    let smart_wallet = state.smart_wallet;
+#[receive(
+    contract = "gona_stake",
+    name = "view_reward_volume",
+    return_value = "u64"
+)]
+fn view_reward(_ctx: &ReceiveContext, host: &Host<State>) -> ReceiveResult<u64> {
+    Ok(host.state().reward_volume)
+}
 
-    // if days == 0 and you calculate reward. it will change balance to 0
-    if days_of_stake > 0 {
-        let rewards = calculate_percent(amount.0, weight, decimals);
-        let cumulative_rewards = rewards * days_of_stake;
-        ensure!(reward_volume >= rewards, StakingError::Overflow.into());
-        state.reward_volume -= cumulative_rewards;
-        amount += TokenAmountU64(cumulative_rewards);
-        //bail!(StakingError::TransferError.into());
-    }
+/// Updates one of the settings of the contract. The settings share this
+/// entrypoint to keep the module within the limit on exported functions.
+#[receive(
+    contract = "gona_stake",
+    name = "update_settings",
+    parameter = "SettingUpdate",
+    error = "StakingError",
+    enable_logger,
+    mutable
+)]
+fn update_settings(
+    ctx: &ReceiveContext,
+    host: &mut Host<State>,
+    logger: &mut Logger,
+) -> ReceiveResult<()> {
+    let update: SettingUpdate = ctx.parameter_cursor().get()?;
+    let previous = host.state.admin;
+    ensure_eq!(
+        ctx.sender(),
+        previous,
+        StakingError::SenderIsNotAdmin.into()
+    );
+    let reward_volume = host.state.reward_volume;
+    host.state_mut()
+        .update_setting(update.clone(), ctx.metadata().slot_time())?;
+    match update {
+        SettingUpdate::Admin(admin) => {
+            logger.log(&StakingEvent::AdminChanged { previous, admin })?
+        }
+        update => logger.log(&StakingEvent::SettingUpdated(update))?,
+    }
+    // changing the accrual granularity first accrues the liquid pool
+    log_reward_volume(logger, host.state(), reward_volume)
+}
 
-    let owned_entry = OwnedEntrypointName::new_unchecked("depositCis2Tokens".into());
-    // Create a Transfer instance
-    let transfer_payload = Transfer {
-        token_id: TOKEN_ID,
-        amount,
-        to: Receiver::Contract(smart_wallet, owned_entry),
-        from: Address::Contract(ctx.self_address()),
-        data: AdditionalData::from(to_bytes(&param.staker)),
+#[receive(
+    contract = "gona_stake",
+    name = "view_settings",
+    return_value = "Settings"
+)]
+fn view_settings(_ctx: &ReceiveContext, host: &Host<State>) -> ReceiveResult<Settings> {
+    let state = host.state();
+    Ok(Settings {
+        weight: state.weight,
+        paused: state.paused,
+        emergency: state.emergency,
+        payout_allowlist_enabled: state.payout_allowlist_enabled,
+        keeper_bounty: state.keeper_bounty,
+        accrual_granularity: state.accrual_granularity,
+        limits: state.limits,
+        penalty_schedule: state.penalty_schedule.clone(),
+        protocol_fee: ProtocolFee {
+            fee_bps: state.protocol_fee_bps,
+            fees_collected: state.fees_collected,
+            fee_collector: state.fee_collector,
+        },
+        referral_bps: state.referral_bps,
+        staker_gating: state.staker_gating,
+    })
+}
+
+/// The principal a staker has staked across its positions and in the liquid
+/// pool, and the principal staked in positions and the liquid pool together.
+#[receive(
+    contract = "gona_stake",
+    name = "view_staked",
+    parameter = "Staker",
+    return_value = "StakedTotals"
+)]
+fn view_staked(ctx: &ReceiveContext, host: &Host<State>) -> ReceiveResult<StakedTotals> {
+    let staker: Staker = ctx.parameter_cursor().get()?;
+    let state = host.state();
+    Ok(StakedTotals {
+        staker: state.staker_total(&staker),
+        total: state.pool_total(),
+    })
+}
+
+#[receive(
+    contract = "gona_stake",
+    name = "calculate_rewards",
+    parameter = "PositionId",
+    return_value = "RewardResult",
+    error = "StakingError"
+)]
+fn calculate_rewards(ctx: &ReceiveContext, host: &Host<State>) -> ReceiveResult<RewardResult> {
+    let position: PositionId = ctx.parameter_cursor().get()?;
+    if let Some(stake_entry) = host.state.stake_entries.get(&position) {
+        let now = ctx.metadata().slot_time();
+        let days = now
+            .duration_since(stake_entry.time_of_stake)
+            .ok_or(StakingError::DaysOfStakeCouldNotBeCalculated)?
+            .days();
+        let rewards = host.state.rewards_due(
+            stake_entry.amount.0,
+            stake_entry.time_of_stake,
+            stake_entry.reward_epoch,
+            now,
+        );
+        Ok(RewardResult {
+            days,
+            rewards,
+            amount_staked: stake_entry.amount.0,
+        })
+    } else {
+        bail!(StakingError::StakingNotFound.into())
+    }
+}
+
+// Helper Functions
+
+/// Milliseconds in the year the annual reward rate is spread over.
+const YEAR_MILLIS: u128 = 365 * 24 * 60 * 60 * 1000;
+
+/// calculate the reward `amount` accrues over `millis` milliseconds.
+/// the calculate is done using the decimals of the token.
+/// this fn assumes that the amount and weight is `n * 10.pow(decimals)`,
+/// `weight` being the daily rate so that the annual rate is `weight * 365`.
+/// The result is rounded down once, in favour of the pool.
+fn accrued_rewards(amount: u64, weight: u32, decimals: u8, millis: u64) -> u64 {
+    let annual_rate = u128::from(weight) * 365;
+    let scale = 100 * 10_u128.pow(decimals.into());
+    let rewards = u128::from(amount) * annual_rate * u128::from(millis) / (scale * YEAR_MILLIS);
+    u64::try_from(rewards).unwrap_or(u64::MAX)
+}
+
+#[receive(
+    contract = "gona_stake",
+    name = "unstake",
+    error = "StakingError",
+    parameter = "UnstakeParam",
+    crypto_primitives,
+    enable_logger,
+    mutable
+)]
+fn unstake(
+    ctx: &ReceiveContext,
+    host: &mut Host<State>,
+    logger: &mut Logger,
+    crypto_primitives: &impl HasCryptoPrimitives,
+) -> ReceiveResult<()> {
+    let param: UnstakeParam = ctx.parameter_cursor().get()?;
+    unstake_internal(ctx, host, logger, crypto_primitives, param, None)
+}
+
+/// Unstakes on behalf of the owner of the position, either called directly or
+/// through a `permit` signed by `signer`.
+fn unstake_internal(
+    ctx: &ReceiveContext,
+    host: &mut Host<State>,
+    logger: &mut Logger,
+    crypto_primitives: &impl HasCryptoPrimitives,
+    param: UnstakeParam,
+    signer: Option<PublicKeyEd25519>,
+) -> ReceiveResult<()> {
+    let (
+        owner,
+        previous_amount,
+        time_of_stake,
+        reward_epoch,
+        mut pending_rewards,
+        smart_wallet,
+        holder,
+        referrer,
+    ) = {
+        let stake_entry = host
+            .state()
+            .stake_entries
+            .get(&param.position)
+            .ok_or(StakingError::StakingNotFound)?;
+        (
+            stake_entry.owner,
+            stake_entry.amount,
+            stake_entry.time_of_stake,
+            stake_entry.reward_epoch,
+            stake_entry.pending_rewards,
+            stake_entry.smart_wallet,
+            position_holder(&stake_entry)?,
+            stake_entry.referrer,
+        )
+    };
+    let payout_signer = authorize_payout(
+        ctx,
+        host.state_mut(),
+        logger,
+        crypto_primitives,
+        &param,
+        owner,
+        signer,
+    )?;
+    ensure_authorized(ctx, host.state(), &owner, signer.or(payout_signer))?;
+    let state = host.state_mut();
+    let reward_volume = state.reward_volume;
+    let now = ctx.metadata().slot_time();
+    state.roll_epochs(now);
+
+    let (to, data) = match param.payout {
+        Some(payout) => (payout.to, payout.data),
+        None => staker_receiver(&owner, smart_wallet)?,
     };
-    let entry_point = EntrypointName::new_unchecked("transfer".into());
 
-    let mut transfers = Vec::new();
-    transfers.push(transfer_payload);
-    let payload = TransferParams::from(transfers);
-    // calculate transfer after withdrawal; if amount is less than 0.001 flush the account
+    ensure!(
+        previous_amount.0.ge(&param.amount.0),
+        StakingError::InsufficientFunds.into()
+    );
+    ensure!(
+        now >= time_of_stake,
+        StakingError::DaysOfStakeCouldNotBeCalculated.into()
+    );
+
+    let mut amount = param.amount;
+    let mut deferred = None;
+    let mut rewards = 0;
+    let mut fee = 0;
+    let mut referral = None;
+
+    let cumulative_rewards = state.rewards_due(amount.0, time_of_stake, reward_epoch, now);
+    let (principal_penalty, rewards_penalty) =
+        state.early_exit_penalty(time_of_stake, now, amount.0, cumulative_rewards);
+    amount -= TokenAmountU64(principal_penalty);
+    let cumulative_rewards = cumulative_rewards - rewards_penalty;
+    if cumulative_rewards > 0 {
+        // pay out what the pool can afford, the rest is owed to the staker
+        let paid = state.take_rewards(cumulative_rewards);
+        rewards = paid;
+        fee = state.take_protocol_fee(paid);
+        referral = state.credit_referrer(referrer, paid);
+        amount += TokenAmountU64(paid - fee);
+        if paid < cumulative_rewards {
+            let owed = cumulative_rewards - paid;
+            pending_rewards += owed;
+            deferred = Some((paid, owed));
+        }
+    }
+
+    // calculate transfer after withdrawal; if amount is less than the dust threshold flush the account
     let balance = previous_amount.0 - param.amount.0;
+    let is_dust = balance < state.limits.dust_threshold;
+    let remaining = if is_dust { 0 } else { balance };
+    if is_dust {
+        // the flushed dust is paid out with the unstake
+        amount += TokenAmountU64(balance);
+    }
 
-    if balance < 1000 {
-        state.stake_entries.remove(&param.staker);
+    let closed = is_dust && pending_rewards == 0;
+    let removed = if is_dust {
+        previous_amount.0
+    } else {
+        param.amount.0
+    };
+    state.remove_stake(&owner, reward_epoch, removed);
+    if closed {
+        state.stake_entries.remove(&param.position);
     } else {
+        // keep the entry around while rewards are still owed to the staker
         state
             .stake_entries
-            .entry(param.staker.clone())
+            .entry(param.position)
             .and_modify(|stake| {
-                stake.amount = TokenAmountU64(balance);
+                stake.amount = TokenAmountU64(remaining);
+                stake.pending_rewards = pending_rewards;
             });
     }
 
-    host.invoke_contract(&token_address, &payload, entry_point, Amount::zero())?;
+    transfer_tokens(host, ctx.self_address(), to, data, amount)?;
+    collect_penalty(
+        ctx,
+        host,
+        logger,
+        param.position,
+        owner,
+        principal_penalty,
+        rewards_penalty,
+    )?;
 
+    if let Some((paid, owed)) = deferred {
+        logger.log(&StakingEvent::PartialRewardPayout {
+            position: param.position,
+            staker: owner,
+            paid: TokenAmountU64(paid),
+            owed: TokenAmountU64(owed),
+        })?;
+    }
     logger.log(&StakingEvent::Unstaking {
+        position: param.position,
         amount: param.amount,
-        staker: param.staker,
+        rewards: TokenAmountU64(rewards),
+        fee: TokenAmountU64(fee),
+        remaining: TokenAmountU64(remaining),
+        staker: owner,
         time: ctx.metadata().slot_time(),
     })?;
+    log_referral_reward(logger, param.position, referral)?;
+    if closed {
+        log_position_burn(logger, param.position, holder)?;
+    }
+    log_reward_volume(logger, host.state(), reward_volume)
+}
+
+/// Sends the penalty of an early unstake where the penalty schedule directs
+/// it. Penalties on rewards headed for the reward volume simply stay there.
+fn collect_penalty(
+    ctx: &ReceiveContext,
+    host: &mut Host<State>,
+    logger: &mut Logger,
+    position: PositionId,
+    staker: Staker,
+    principal_penalty: u64,
+    rewards_penalty: u64,
+) -> ReceiveResult<()> {
+    if principal_penalty + rewards_penalty == 0 {
+        return Ok(());
+    }
+    let state = host.state_mut();
+    let Some(schedule) = state.penalty_schedule.clone() else {
+        return Ok(());
+    };
+    let (amount, treasury) = match schedule.destination {
+        PenaltyDestination::RewardVolume => {
+            state.reward_volume += principal_penalty;
+            (principal_penalty + rewards_penalty, None)
+        }
+        PenaltyDestination::Treasury(payout) => {
+            let amount = principal_penalty + state.take_rewards(rewards_penalty);
+            let treasury = payout.to.address();
+            transfer_tokens(
+                host,
+                ctx.self_address(),
+                payout.to,
+                payout.data,
+                TokenAmountU64(amount),
+            )?;
+            (amount, Some(treasury))
+        }
+    };
+    logger.log(&StakingEvent::EarlyExitPenalty {
+        position,
+        staker,
+        amount: TokenAmountU64(amount),
+        treasury,
+    })?;
+    Ok(())
+}
+
+/// Quotes what unstaking an amount of a position right now would pay out,
+/// including the early exit penalty.
+#[receive(
+    contract = "gona_stake",
+    name = "quote_unstake",
+    parameter = "QuoteUnstakeParam",
+    return_value = "UnstakeQuote",
+    error = "StakingError"
+)]
+fn quote_unstake(ctx: &ReceiveContext, host: &Host<State>) -> ReceiveResult<UnstakeQuote> {
+    let param: QuoteUnstakeParam = ctx.parameter_cursor().get()?;
+    let state = host.state();
+    let now = ctx.metadata().slot_time();
+    let stake_entry = state
+        .stake_entries
+        .get(&param.position)
+        .ok_or(StakingError::StakingNotFound)?;
+    ensure!(
+        stake_entry.amount >= param.amount,
+        StakingError::InsufficientFunds.into()
+    );
+    let rewards = state.rewards_due(
+        param.amount.0,
+        stake_entry.time_of_stake,
+        stake_entry.reward_epoch,
+        now,
+    );
+    let (principal_penalty, rewards_penalty) =
+        state.early_exit_penalty(stake_entry.time_of_stake, now, param.amount.0, rewards);
+    let rewards = rewards - rewards_penalty;
+    let paid = rewards.min(state.reward_volume);
+    let fee = state.protocol_fee(paid);
+    let balance = stake_entry.amount.0 - param.amount.0;
+    let dust = if balance < state.limits.dust_threshold {
+        balance
+    } else {
+        0
+    };
+    Ok(UnstakeQuote {
+        rewards: paid,
+        owed: rewards - paid,
+        penalty: principal_penalty + rewards_penalty,
+        fee,
+        payout: param.amount.0 - principal_penalty + paid - fee + dust,
+    })
+}
+
+/// Sends collected protocol fees to a receiver chosen by the fee collector.
+#[receive(
+    contract = "gona_stake",
+    name = "withdraw_fees",
+    parameter = "WithdrawFeesParam",
+    error = "StakingError",
+    enable_logger,
+    mutable
+)]
+fn withdraw_fees(
+    ctx: &ReceiveContext,
+    host: &mut Host<State>,
+    logger: &mut Logger,
+) -> ReceiveResult<()> {
+    let param: WithdrawFeesParam = ctx.parameter_cursor().get()?;
+    let state = host.state_mut();
+    ensure_eq!(
+        ctx.sender(),
+        state.fee_collector,
+        StakingError::SenderIsNotFeeCollector.into()
+    );
+    ensure!(
+        param.amount.0 <= state.fees_collected,
+        StakingError::InsufficientFunds.into()
+    );
+    state.fees_collected -= param.amount.0;
+    let to = param.payout.to.address();
+    transfer_tokens(
+        host,
+        ctx.self_address(),
+        param.payout.to,
+        param.payout.data,
+        param.amount,
+    )?;
+    logger.log(&StakingEvent::FeesWithdrawn {
+        to,
+        amount: param.amount,
+    })?;
+    Ok(())
+}
+
+#[receive(
+    contract = "gona_stake",
+    name = "claim_rewards",
+    error = "StakingError",
+    parameter = "PositionId",
+    enable_logger,
+    mutable
+)]
+fn claim_rewards(
+    ctx: &ReceiveContext,
+    host: &mut Host<State>,
+    logger: &mut Logger,
+) -> ReceiveResult<()> {
+    let position: PositionId = ctx.parameter_cursor().get()?;
+    claim_rewards_internal(ctx, host, logger, position, None)
+}
+
+/// Claims pending rewards on behalf of the owner of the position, either
+/// called directly or through a `permit` signed by `signer`.
+fn claim_rewards_internal(
+    ctx: &ReceiveContext,
+    host: &mut Host<State>,
+    logger: &mut Logger,
+    position: PositionId,
+    signer: Option<PublicKeyEd25519>,
+) -> ReceiveResult<()> {
+    let state = host.state_mut();
+    let reward_volume = state.reward_volume;
+
+    let (owner, amount, pending_rewards, smart_wallet, holder, referrer) = {
+        let stake_entry = state
+            .stake_entries
+            .get(&position)
+            .ok_or(StakingError::StakingNotFound)?;
+        (
+            stake_entry.owner,
+            stake_entry.amount,
+            stake_entry.pending_rewards,
+            stake_entry.smart_wallet,
+            position_holder(&stake_entry)?,
+            stake_entry.referrer,
+        )
+    };
+    ensure_authorized(ctx, state, &owner, signer)?;
+    ensure!(pending_rewards > 0, StakingError::NoPendingRewards.into());
+
+    let paid = state.take_rewards(pending_rewards);
+    ensure!(paid > 0, StakingError::RewardPoolDepleted.into());
+    let remaining = pending_rewards - paid;
+    let fee = state.take_protocol_fee(paid);
+    let referral = state.credit_referrer(referrer, paid);
+
+    let closed = remaining == 0 && amount.0 == 0;
+    if closed {
+        state.stake_entries.remove(&position);
+    } else {
+        state.stake_entries.entry(position).and_modify(|stake| {
+            stake.pending_rewards = remaining;
+        });
+    }
+
+    transfer_to_staker(
+        host,
+        ctx.self_address(),
+        &owner,
+        smart_wallet,
+        TokenAmountU64(paid - fee),
+    )?;
+
+    logger.log(&StakingEvent::RewardsClaimed {
+        position,
+        staker: owner,
+        amount: TokenAmountU64(paid - fee),
+        fee: TokenAmountU64(fee),
+        remaining: TokenAmountU64(remaining),
+    })?;
+    log_referral_reward(logger, position, referral)?;
+    if closed {
+        log_position_burn(logger, position, holder)?;
+    }
+    log_reward_volume(logger, host.state(), reward_volume)
+}
+
+/// Transfers `amount` of the staked token from this contract to the staker.
+fn transfer_to_staker(
+    host: &mut Host<State>,
+    self_address: ContractAddress,
+    staker: &Staker,
+    smart_wallet: Option<ContractAddress>,
+    amount: TokenAmountU64,
+) -> ReceiveResult<()> {
+    let (to, data) = staker_receiver(staker, smart_wallet)?;
+    transfer_tokens(host, self_address, to, data, amount)
+}
+
+/// Transfers `amount` of the staked token from this contract to `to`.
+fn transfer_tokens(
+    host: &mut Host<State>,
+    self_address: ContractAddress,
+    to: Receiver,
+    data: AdditionalData,
+    amount: TokenAmountU64,
+) -> ReceiveResult<()> {
+    let token_address = host.state().token_address;
+    // Create a Transfer instance
+    let transfer_payload = Transfer {
+        token_id: TOKEN_ID,
+        amount,
+        to,
+        from: Address::Contract(self_address),
+        data,
+    };
+    let entry_point = EntrypointName::new_unchecked("transfer");
+    let payload = TransferParams::from(vec![transfer_payload]);
+
+    host.invoke_contract(&token_address, &payload, entry_point, Amount::zero())?;
+    Ok(())
+}
+
+/// The default receiver of payouts to a staker. Public keys are paid into
+/// their balance in the smart wallet they staked from, accounts are paid
+/// directly.
+fn staker_receiver(
+    staker: &Staker,
+    smart_wallet: Option<ContractAddress>,
+) -> Result<(Receiver, AdditionalData), StakingError> {
+    match staker {
+        Staker::PublicKey(key) => {
+            let smart_wallet = smart_wallet.ok_or(StakingError::InvalidStakingState)?;
+            let owned_entry = OwnedEntrypointName::new_unchecked("depositCis2Tokens".into());
+            Ok((
+                Receiver::Contract(smart_wallet, owned_entry),
+                AdditionalData::from(to_bytes(key)),
+            ))
+        }
+        Staker::Address(Address::Account(account)) => {
+            Ok((Receiver::Account(*account), AdditionalData::empty()))
+        }
+        // a contract has to name the entrypoint it wants to be paid to
+        Staker::Address(Address::Contract(_)) => Err(StakingError::PayoutReceiverRequired),
+    }
+}
+
+/// Checks that the owner authorized paying the unstake out to its custom
+/// payout, if it names one. Addresses are authorized by being the sender,
+/// public keys have to sign the `PayoutMessage` unless the whole unstake came
+/// through a `permit` or was sent by an operator the key approved. The nonce
+/// a signature uses is logged like in `permit`. Returns the key that signed,
+/// whose signature also authorizes the unstake itself.
+fn authorize_payout(
+    ctx: &ReceiveContext,
+    state: &mut State,
+    logger: &mut Logger,
+    crypto_primitives: &impl HasCryptoPrimitives,
+    param: &UnstakeParam,
+    owner: Staker,
+    signer: Option<PublicKeyEd25519>,
+) -> ReceiveResult<Option<PublicKeyEd25519>> {
+    let Some(payout) = &param.payout else {
+        return Ok(None);
+    };
+    if state.payout_allowlist_enabled {
+        ensure!(
+            state.payout_allowlist.contains(&payout.to.address()),
+            StakingError::ReceiverNotAllowed.into()
+        );
+    }
+    let key = match owner {
+        Staker::PublicKey(_) if signer.is_some() => return Ok(None),
+        Staker::PublicKey(_) if state.is_staker_operator(&owner, &ctx.sender()) => return Ok(None),
+        Staker::PublicKey(key) => key,
+        Staker::Address(_) => return Ok(None),
+    };
+    let signature = param
+        .signature
+        .as_ref()
+        .ok_or(StakingError::MissingSignature)?;
+    ensure!(
+        signature.expiry_time > ctx.metadata().slot_time(),
+        StakingError::Expired.into()
+    );
+    state.use_nonce(key, signature.nonce)?;
+
+    let message = PayoutMessage {
+        contract_address: ctx.self_address(),
+        staker: key,
+        position: param.position,
+        amount: param.amount,
+        payout: payout.clone(),
+        nonce: signature.nonce,
+        expiry_time: signature.expiry_time,
+    };
+    let message_hash = crypto_primitives.hash_sha2_256(&to_bytes(&message)).0;
+    ensure!(
+        crypto_primitives.verify_ed25519_signature(key, signature.signature, &message_hash),
+        StakingError::WrongSignature.into()
+    );
+    logger.log(&StakingEvent::Nonce {
+        signer: key,
+        nonce: signature.nonce,
+    })?;
+    Ok(Some(key))
+}
+
+/// Works out who a CIS-2 transfer into `stake` is staking for. Transfers
+/// from the smart wallet carry the staker's public key as data, while an
+/// account stakes for itself. Any data that follows is the staker who
+/// referred the stake.
+fn staker_from_transfer(
+    from: Address,
+    data: &AdditionalData,
+) -> Result<(Staker, Option<Staker>), StakingError> {
+    let mut cursor = Cursor::new(data.as_ref());
+    let staker = match from {
+        Address::Account(_) => Staker::Address(from),
+        Address::Contract(_) => Staker::PublicKey(
+            PublicKeyEd25519::deserial(&mut cursor)
+                .map_err(|_| StakingError::CouldNotParseAdditionalData)?,
+        ),
+    };
+    let referrer = if cursor.offset < data.as_ref().len() {
+        let referrer =
+            Staker::deserial(&mut cursor).map_err(|_| StakingError::CouldNotParseAdditionalData)?;
+        Some(referrer)
+    } else {
+        None
+    };
+    Ok((staker, referrer))
+}
+
+/// Logs the reward volume if it changed from `before` during the call.
+fn log_reward_volume(logger: &mut Logger, state: &State, before: u64) -> ReceiveResult<()> {
+    if state.reward_volume != before {
+        logger.log(&StakingEvent::RewardVolumeChanged {
+            reward_volume: TokenAmountU64(state.reward_volume),
+        })?;
+    }
+    Ok(())
+}
+
+/// Logs the referral reward credited for rewards paid on `position`, if any.
+fn log_referral_reward(
+    logger: &mut Logger,
+    position: PositionId,
+    referral: Option<(Staker, u64)>,
+) -> ReceiveResult<()> {
+    if let Some((referrer, amount)) = referral {
+        logger.log(&StakingEvent::ReferralReward {
+            position,
+            referrer,
+            amount: TokenAmountU64(amount),
+        })?;
+    }
+    Ok(())
+}
+
+/// Public keys can only stake through a trusted smart wallet, which is also
+/// where their payouts are sent back to.
+fn staker_smart_wallet(
+    state: &State,
+    staker: &Staker,
+    from: Address,
+) -> Result<Option<ContractAddress>, StakingError> {
+    match (staker, from) {
+        (Staker::PublicKey(_), Address::Contract(wallet))
+            if state.smart_wallets.contains(&wallet) =>
+        {
+            Ok(Some(wallet))
+        }
+        (Staker::PublicKey(_), _) => Err(StakingError::UntrustedSmartWallet),
+        (Staker::Address(_), _) => Ok(None),
+    }
+}
+
+/// The address holding the NFT of a position: the smart wallet of a public
+/// key owner, or the owning address itself.
+fn position_holder(entry: &StakeEntry) -> Result<Address, StakingError> {
+    match (entry.owner, entry.smart_wallet) {
+        (_, Some(smart_wallet)) => Ok(Address::Contract(smart_wallet)),
+        (Staker::Address(address), None) => Ok(address),
+        (Staker::PublicKey(_), None) => Err(StakingError::InvalidStakingState),
+    }
+}
+
+fn log_position_burn(
+    logger: &mut Logger,
+    position: PositionId,
+    holder: Address,
+) -> ReceiveResult<()> {
+    logger.log(&StakingEvent::Burn(BurnEvent {
+        token_id: position_token_id(position),
+        amount: TokenAmountU64(1),
+        owner: holder,
+    }))?;
+    Ok(())
+}
+
+/// Credits tokens minted to a smart wallet for a public key to the balance of
+/// that key in the wallet.
+fn deposit_to_smart_wallet(
+    host: &mut Host<State>,
+    self_address: ContractAddress,
+    staker: &Staker,
+    smart_wallet: Option<ContractAddress>,
+    token_id: ReceiptTokenId,
+    amount: TokenAmountU64,
+) -> ReceiveResult<()> {
+    if let (Staker::PublicKey(key), Some(smart_wallet)) = (staker, smart_wallet) {
+        let parameter = OnReceivingCis2Params {
+            token_id,
+            amount,
+            from: Address::Contract(self_address),
+            data: AdditionalData::from(to_bytes(key)),
+        };
+        host.invoke_contract(
+            &smart_wallet,
+            &parameter,
+            EntrypointName::new_unchecked("depositCis2Tokens"),
+            Amount::zero(),
+        )?;
+    }
+    Ok(())
+}
+
+/// Computes `a * b / c` without overflowing the intermediate product.
+fn mul_div(a: u64, b: u64, c: u64) -> u64 {
+    ((a as u128) * (b as u128) / (c as u128)) as u64
+}
+
+/// Stakes can only be managed by their owner or its operators. An address
+/// owns its stakes as the sender, a public key through a `permit` signed by
+/// `signer`, which has to be that key.
+fn ensure_authorized(
+    ctx: &ReceiveContext,
+    state: &State,
+    staker: &Staker,
+    signer: Option<PublicKeyEd25519>,
+) -> ReceiveResult<()> {
+    match (staker, signer) {
+        (Staker::PublicKey(key), Some(signer)) => {
+            ensure_eq!(*key, signer, StakingError::SenderIsNotOwner.into())
+        }
+        (Staker::Address(_), Some(_)) => bail!(StakingError::SenderIsNotOwner.into()),
+        (Staker::PublicKey(_), None) => ensure!(
+            state.is_staker_operator(staker, &ctx.sender()),
+            StakingError::SenderIsNotOwner.into()
+        ),
+        (Staker::Address(address), None) => ensure!(
+            ctx.sender() == *address || state.is_staker_operator(staker, &ctx.sender()),
+            StakingError::SenderIsNotOwner.into()
+        ),
+    }
+    Ok(())
+}
+
+/// Returns the full principal of a position while the admin has enabled
+/// emergency mode. Accrued and pending rewards are forfeited, and the
+/// `paused` flag is not checked so stakers can always leave. Public keys
+/// withdraw through a `permit` or an operator.
+#[receive(
+    contract = "gona_stake",
+    name = "emergency_withdraw",
+    error = "StakingError",
+    parameter = "PositionId",
+    enable_logger,
+    mutable
+)]
+fn emergency_withdraw(
+    ctx: &ReceiveContext,
+    host: &mut Host<State>,
+    logger: &mut Logger,
+) -> ReceiveResult<()> {
+    let position: PositionId = ctx.parameter_cursor().get()?;
+    emergency_withdraw_internal(ctx, host, logger, position, None)
+}
+
+fn emergency_withdraw_internal(
+    ctx: &ReceiveContext,
+    host: &mut Host<State>,
+    logger: &mut Logger,
+    position: PositionId,
+    signer: Option<PublicKeyEd25519>,
+) -> ReceiveResult<()> {
+    let state = host.state_mut();
+    ensure!(state.emergency, StakingError::EmergencyModeDisabled.into());
+
+    let stake_entry = state
+        .stake_entries
+        .remove_and_get(&position)
+        .ok_or(StakingError::StakingNotFound)?;
+    let owner = stake_entry.owner;
+    ensure_authorized(ctx, state, &owner, signer)?;
+    let holder = position_holder(&stake_entry)?;
+    let amount = stake_entry.amount;
+    let smart_wallet = stake_entry.smart_wallet;
+    let now = ctx.metadata().slot_time();
+    state.roll_epochs(now);
+    let forfeited_rewards = state.rewards_due(
+        amount.0,
+        stake_entry.time_of_stake,
+        stake_entry.reward_epoch,
+        now,
+    ) + stake_entry.pending_rewards;
+    state.remove_stake(&owner, stake_entry.reward_epoch, amount.0);
+    stake_entry.delete();
+
+    transfer_to_staker(host, ctx.self_address(), &owner, smart_wallet, amount)?;
+
+    logger.log(&StakingEvent::EmergencyWithdraw {
+        position,
+        staker: owner,
+        amount,
+        forfeited_rewards: TokenAmountU64(forfeited_rewards),
+        time: ctx.metadata().slot_time(),
+    })?;
+    log_position_burn(logger, position, holder)?;
+    Ok(())
+}
+
+/// Pays out the referral rewards of the sender. Public keys claim through a
+/// `permit` naming the trusted smart wallet to be paid into.
+#[receive(
+    contract = "gona_stake",
+    name = "claim_referral_rewards",
+    parameter = "Option<ContractAddress>",
+    error = "StakingError",
+    enable_logger,
+    mutable
+)]
+fn claim_referral_rewards(
+    ctx: &ReceiveContext,
+    host: &mut Host<State>,
+    logger: &mut Logger,
+) -> ReceiveResult<()> {
+    let smart_wallet: Option<ContractAddress> = ctx.parameter_cursor().get()?;
+    claim_referral_rewards_internal(
+        ctx,
+        host,
+        logger,
+        Staker::Address(ctx.sender()),
+        smart_wallet,
+    )
+}
+
+fn claim_referral_rewards_internal(
+    ctx: &ReceiveContext,
+    host: &mut Host<State>,
+    logger: &mut Logger,
+    referrer: Staker,
+    smart_wallet: Option<ContractAddress>,
+) -> ReceiveResult<()> {
+    let state = host.state_mut();
+    if let Staker::PublicKey(_) = referrer {
+        ensure!(
+            smart_wallet.is_some_and(|wallet| state.smart_wallets.contains(&wallet)),
+            StakingError::UntrustedSmartWallet.into()
+        );
+    }
+    let amount = state
+        .referral_stats
+        .get_mut(&referrer)
+        .map_or(0, |mut stats| core::mem::take(&mut stats.claimable));
+    ensure!(amount > 0, StakingError::NoPendingRewards.into());
+
+    transfer_to_staker(
+        host,
+        ctx.self_address(),
+        &referrer,
+        smart_wallet,
+        TokenAmountU64(amount),
+    )?;
+    logger.log(&StakingEvent::ReferralRewardsClaimed {
+        referrer,
+        amount: TokenAmountU64(amount),
+    })?;
+    Ok(())
+}
+
+#[receive(
+    contract = "gona_stake",
+    name = "view_referral_stats",
+    parameter = "Staker",
+    return_value = "ReferralStats"
+)]
+fn view_referral_stats(ctx: &ReceiveContext, host: &Host<State>) -> ReceiveResult<ReferralStats> {
+    let referrer: Staker = ctx.parameter_cursor().get()?;
+    Ok(host
+        .state()
+        .referral_stats
+        .get(&referrer)
+        .map(|stats| stats.clone())
+        .unwrap_or_default())
+}
+
+#[receive(
+    contract = "gona_stake",
+    name = "set_auto_compound",
+    parameter = "SetAutoCompoundParam",
+    error = "StakingError",
+    enable_logger,
+    mutable
+)]
+fn set_auto_compound(
+    ctx: &ReceiveContext,
+    host: &mut Host<State>,
+    logger: &mut Logger,
+) -> ReceiveResult<()> {
+    let param: SetAutoCompoundParam = ctx.parameter_cursor().get()?;
+    set_auto_compound_internal(ctx, host, logger, param, None)
+}
+
+/// Stakes held by a public key change auto compounding through a `permit`
+/// signed by that key or an operator it approved.
+fn set_auto_compound_internal(
+    ctx: &ReceiveContext,
+    host: &mut Host<State>,
+    logger: &mut Logger,
+    param: SetAutoCompoundParam,
+    signer: Option<PublicKeyEd25519>,
+) -> ReceiveResult<()> {
+    let state = host.state_mut();
+    let owner = state
+        .stake_entries
+        .get(&param.position)
+        .map(|entry| entry.owner)
+        .ok_or(StakingError::StakingNotFound)?;
+    ensure_authorized(ctx, state, &owner, signer)?;
+    state
+        .stake_entries
+        .entry(param.position)
+        .and_modify(|stake| stake.auto_compound = param.enabled);
+    logger.log(&StakingEvent::AutoCompoundSet {
+        position: param.position,
+        enabled: param.enabled,
+    })?;
+    Ok(())
+}
+
+/// Rolls the accrued rewards of positions with auto compounding into their
+/// principal. Anyone can call this for any batch of positions and is paid the
+/// keeper bounty for every position compounded.
+#[receive(
+    contract = "gona_stake",
+    name = "compound",
+    parameter = "CompoundParams",
+    error = "StakingError",
+    enable_logger,
+    mutable
+)]
+fn compound(
+    ctx: &ReceiveContext,
+    host: &mut Host<State>,
+    logger: &mut Logger,
+) -> ReceiveResult<()> {
+    let params: CompoundParams = ctx.parameter_cursor().get()?;
+    let now = ctx.metadata().slot_time();
+    let state = host.state_mut();
+    let reward_volume = state.reward_volume;
+    state.roll_epochs(now);
+    let mut compounded = 0;
+    let mut compounded_rewards = 0;
+    for position in params.positions {
+        if let Some((staker, rewards, owed, amount)) = state.compound_position(position, now) {
+            // positions the pool paid nothing for do not earn the keeper a bounty
+            if rewards > 0 {
+                compounded += 1;
+                compounded_rewards += rewards;
+            }
+            if owed > 0 {
+                logger.log(&StakingEvent::PartialRewardPayout {
+                    position,
+                    staker,
+                    paid: TokenAmountU64(rewards),
+                    owed: TokenAmountU64(owed),
+                })?;
+            }
+            logger.log(&StakingEvent::Compounded {
+                position,
+                staker,
+                rewards: TokenAmountU64(rewards),
+                amount,
+            })?;
+            let referrer = state
+                .stake_entries
+                .get(&position)
+                .and_then(|entry| entry.referrer);
+            let referral = state.credit_referrer(referrer, rewards);
+            log_referral_reward(logger, position, referral)?;
+        }
+    }
+    pay_keeper(ctx, host, logger, compounded, compounded_rewards)?;
+    log_reward_volume(logger, host.state(), reward_volume)
+}
+
+/// Pays the invoker of a maintenance call the keeper bounty for the `items`
+/// it processed, as far as the reward volume allows. The bounty is capped at
+/// a share of the `rewards` the call moved, so processing dust positions does
+/// not pay more than it is worth.
+fn pay_keeper(
+    ctx: &ReceiveContext,
+    host: &mut Host<State>,
+    logger: &mut Logger,
+    items: u64,
+    rewards: u64,
+) -> ReceiveResult<()> {
+    let state = host.state_mut();
+    let keeper_bounty = state.keeper_bounty;
+    let bounty = keeper_bounty
+        .per_item
+        .saturating_mul(items)
+        .min(keeper_bounty.max_per_call)
+        .min(mul_div(rewards, keeper_bounty.max_share_bps.into(), 10_000));
+    let bounty = state.take_rewards(bounty);
+    if bounty == 0 {
+        return Ok(());
+    }
+    let keeper = ctx.invoker();
+    transfer_tokens(
+        host,
+        ctx.self_address(),
+        Receiver::Account(keeper),
+        AdditionalData::empty(),
+        TokenAmountU64(bounty),
+    )?;
+    logger.log(&StakingEvent::KeeperRewarded {
+        keeper,
+        bounty: TokenAmountU64(bounty),
+    })?;
+    Ok(())
+}
+
+// ======== Epochs ========
+
+#[receive(
+    contract = "gona_stake",
+    name = "view_epoch",
+    return_value = "EpochInfo",
+    error = "StakingError"
+)]
+fn view_epoch(ctx: &ReceiveContext, host: &Host<State>) -> ReceiveResult<EpochInfo> {
+    let mut epochs = host
+        .state()
+        .epochs
+        .ok_or(StakingError::EpochsNotConfigured)?;
+    epochs.roll(ctx.metadata().slot_time());
+    Ok(EpochInfo {
+        current: epochs.current,
+        next_epoch_start: epochs
+            .current_start
+            .checked_add(epochs.length)
+            .ok_or(StakingError::Overflow)?,
+        budget: epochs.budget,
+    })
+}
+
+/// Approves or revokes operators that can unstake, claim rewards and set
+/// auto compounding of the positions of the sender. Public keys update their operators through
+/// `permit`.
+#[receive(
+    contract = "gona_stake",
+    name = "update_staker_operators",
+    parameter = "UpdateOperatorParams",
+    error = "StakingError",
+    enable_logger,
+    mutable
+)]
+fn update_staker_operators(
+    ctx: &ReceiveContext,
+    host: &mut Host<State>,
+    logger: &mut Logger,
+) -> ReceiveResult<()> {
+    let params: UpdateOperatorParams = ctx.parameter_cursor().get()?;
+    update_staker_operators_internal(host, logger, Staker::Address(ctx.sender()), params)
+}
+
+fn update_staker_operators_internal(
+    host: &mut Host<State>,
+    logger: &mut Logger,
+    owner: Staker,
+    params: UpdateOperatorParams,
+) -> ReceiveResult<()> {
+    let state = host.state_mut();
+    for param in params.0 {
+        state.update_staker_operator(param.update, owner, param.operator);
+        logger.log(&StakingEvent::OperatorUpdated {
+            owner,
+            operator: param.operator,
+            update: param.update,
+        })?;
+    }
+    Ok(())
+}
+
+#[receive(
+    contract = "gona_stake",
+    name = "staker_operator_of",
+    parameter = "StakerOperatorOfQueryParams",
+    return_value = "OperatorOfQueryResponse"
+)]
+fn staker_operator_of(
+    ctx: &ReceiveContext,
+    host: &Host<State>,
+) -> ReceiveResult<OperatorOfQueryResponse> {
+    let params: StakerOperatorOfQueryParams = ctx.parameter_cursor().get()?;
+    let results: Vec<bool> = params
+        .queries
+        .iter()
+        .map(|query| {
+            host.state()
+                .is_staker_operator(&query.owner, &query.address)
+        })
+        .collect();
+    Ok(OperatorOfQueryResponse::from(results))
+}
+
+/// The entrypoints that can be called through `permit`.
+const PERMIT_ENTRYPOINTS: [EntrypointName; 6] = [
+    EntrypointName::new_unchecked("unstake"),
+    EntrypointName::new_unchecked("claim_rewards"),
+    EntrypointName::new_unchecked("update_staker_operators"),
+    EntrypointName::new_unchecked("set_auto_compound"),
+    EntrypointName::new_unchecked("claim_referral_rewards"),
+    EntrypointName::new_unchecked("emergency_withdraw"),
+];
+
+/// Verifies an Ed25519 signed message and calls the entrypoint it is meant
+/// for on behalf of the signer. This lets a sponsor send the transaction for
+/// public key stakers that hold no CCD.
+#[receive(
+    contract = "gona_stake",
+    name = "permit",
+    parameter = "PermitParam",
+    error = "StakingError",
+    crypto_primitives,
+    enable_logger,
+    mutable
+)]
+fn permit(
+    ctx: &ReceiveContext,
+    host: &mut Host<State>,
+    logger: &mut Logger,
+    crypto_primitives: &impl HasCryptoPrimitives,
+) -> ReceiveResult<()> {
+    let param: PermitParam = ctx.parameter_cursor().get()?;
+    let message = param.message;
+
+    ensure_eq!(
+        message.contract_address,
+        ctx.self_address(),
+        StakingError::WrongContract.into()
+    );
+    ensure!(
+        message.timestamp > ctx.metadata().slot_time(),
+        StakingError::Expired.into()
+    );
+    host.state_mut().use_nonce(param.signer, message.nonce)?;
+
+    let message_hash = crypto_primitives.hash_sha2_256(&to_bytes(&message)).0;
+    ensure!(
+        crypto_primitives.verify_ed25519_signature(param.signer, param.signature, &message_hash),
+        StakingError::WrongSignature.into()
+    );
+
+    let entry_point = message.entry_point.as_entrypoint_name();
+    if entry_point == PERMIT_ENTRYPOINTS[0] {
+        let payload: UnstakeParam = from_bytes(&message.payload)?;
+        unstake_internal(
+            ctx,
+            host,
+            logger,
+            crypto_primitives,
+            payload,
+            Some(param.signer),
+        )?;
+    } else if entry_point == PERMIT_ENTRYPOINTS[1] {
+        let payload: PositionId = from_bytes(&message.payload)?;
+        claim_rewards_internal(ctx, host, logger, payload, Some(param.signer))?;
+    } else if entry_point == PERMIT_ENTRYPOINTS[2] {
+        let payload: UpdateOperatorParams = from_bytes(&message.payload)?;
+        update_staker_operators_internal(host, logger, Staker::PublicKey(param.signer), payload)?;
+    } else if entry_point == PERMIT_ENTRYPOINTS[3] {
+        let payload: SetAutoCompoundParam = from_bytes(&message.payload)?;
+        set_auto_compound_internal(ctx, host, logger, payload, Some(param.signer))?;
+    } else if entry_point == PERMIT_ENTRYPOINTS[4] {
+        let payload: Option<ContractAddress> = from_bytes(&message.payload)?;
+        claim_referral_rewards_internal(
+            ctx,
+            host,
+            logger,
+            Staker::PublicKey(param.signer),
+            payload,
+        )?;
+    } else if entry_point == PERMIT_ENTRYPOINTS[5] {
+        let payload: PositionId = from_bytes(&message.payload)?;
+        emergency_withdraw_internal(ctx, host, logger, payload, Some(param.signer))?;
+    } else {
+        bail!(StakingError::WrongEntryPoint.into());
+    }
+
+    logger.log(&StakingEvent::Nonce {
+        signer: param.signer,
+        nonce: message.nonce,
+    })?;
+    Ok(())
+}
+
+/// Returns the hash a public key has to sign for a `permit` message.
+#[receive(
+    contract = "gona_stake",
+    name = "viewMessageHash",
+    parameter = "PermitMessage",
+    return_value = "[u8;32]",
+    crypto_primitives
+)]
+fn view_message_hash(
+    ctx: &ReceiveContext,
+    _host: &Host<State>,
+    crypto_primitives: &impl HasCryptoPrimitives,
+) -> ReceiveResult<[u8; 32]> {
+    let message: PermitMessage = ctx.parameter_cursor().get()?;
+    Ok(crypto_primitives.hash_sha2_256(&to_bytes(&message)).0)
+}
+
+#[receive(
+    contract = "gona_stake",
+    name = "supportsPermit",
+    parameter = "SupportsPermitQueryParams",
+    return_value = "SupportsQueryResponse"
+)]
+fn supports_permit(
+    ctx: &ReceiveContext,
+    _host: &Host<State>,
+) -> ReceiveResult<SupportsQueryResponse> {
+    let params: SupportsPermitQueryParams = ctx.parameter_cursor().get()?;
+    let results: Vec<SupportResult> = params
+        .queries
+        .iter()
+        .map(|entry_point| {
+            if PERMIT_ENTRYPOINTS.contains(&entry_point.as_entrypoint_name()) {
+                SupportResult::Support
+            } else {
+                SupportResult::NoSupport
+            }
+        })
+        .collect();
+    Ok(SupportsQueryResponse::from(results))
+}
+
+/// Returns the next nonce of each public key.
+#[receive(
+    contract = "gona_stake",
+    name = "nonceOf",
+    parameter = "NonceOfQueryParams",
+    return_value = "NonceOfQueryResponse"
+)]
+fn nonce_of(ctx: &ReceiveContext, host: &Host<State>) -> ReceiveResult<NonceOfQueryResponse> {
+    let params: NonceOfQueryParams = ctx.parameter_cursor().get()?;
+    let nonces = params
+        .queries
+        .iter()
+        .map(|key| host.state().nonces.get(key).map_or(0, |nonce| *nonce))
+        .collect();
+    Ok(NonceOfQueryResponse(nonces))
+}
+
+/// Answers CIS-0 queries for the standards this contract supports.
+#[receive(
+    contract = "gona_stake",
+    name = "supports",
+    parameter = "SupportsQueryParams",
+    return_value = "SupportsQueryResponse",
+    error = "StakingError"
+)]
+fn supports(ctx: &ReceiveContext, _host: &Host<State>) -> ReceiveResult<SupportsQueryResponse> {
+    let params: SupportsQueryParams = ctx.parameter_cursor().get()?;
+    let results: Vec<SupportResult> = params
+        .queries
+        .iter()
+        .map(|standard| {
+            if SUPPORTED_STANDARDS.contains(&standard.as_standard_identifier()) {
+                SupportResult::Support
+            } else {
+                SupportResult::NoSupport
+            }
+        })
+        .collect();
+    Ok(SupportsQueryResponse::from(results))
+}
+
+// ======== Liquid Staking ========
+
+/// Stakes into the liquid pool and mints receipt tokens for the share of the
+/// pool the stake buys. Stakers are identified like in `stake`; receipt
+/// tokens of a public key are deposited to its balance in the smart wallet.
+#[receive(
+    contract = "gona_stake",
+    name = "stake_liquid",
+    error = "StakingError",
+    parameter = "OnReceivingCis2Params<ContractTokenId,ContractTokenAmount>",
+    enable_logger,
+    mutable
+)]
+fn stake_liquid(
+    ctx: &ReceiveContext,
+    host: &mut Host<State>,
+    logger: &mut Logger,
+) -> ReceiveResult<()> {
+    let parameter: OnReceivingCis2Params<ContractTokenId, ContractTokenAmount> =
+        ctx.parameter_cursor().get()?;
+    let amount = parameter.amount;
+
+    ensure_eq!(
+        ctx.sender(),
+        Address::Contract(host.state().token_address),
+        StakingError::SenderContractAddressIsNotAllowedToStake.into()
+    );
+    let (staker, _) = staker_from_transfer(parameter.from, &parameter.data)?;
+    host.state().ensure_stake_limits(&staker, amount.0)?;
+    host.state().ensure_may_stake(&staker)?;
+    let smart_wallet = staker_smart_wallet(host.state(), &staker, parameter.from)?;
+    let owner = smart_wallet.map_or(parameter.from, Address::Contract);
+
+    let state = host.state_mut();
+    let reward_volume = state.reward_volume;
+    state.accrue_liquid_rewards(ctx.metadata().slot_time());
+    let shares = TokenAmountU64(state.deposit_liquid(amount.0));
+    state.mint_receipts(owner, shares);
+    // liquid principal counts towards the per staker maximum
+    *state.staker_totals.entry(staker).or_insert(0) += amount.0;
+
+    logger.log(&StakingEvent::Mint(MintEvent {
+        token_id: RECEIPT_TOKEN_ID,
+        amount: shares,
+        owner,
+    }))?;
+    logger.log(&StakingEvent::LiquidStaked {
+        staker,
+        amount,
+        shares,
+    })?;
+    log_reward_volume(logger, host.state(), reward_volume)?;
+
+    deposit_to_smart_wallet(
+        host,
+        ctx.self_address(),
+        &staker,
+        smart_wallet,
+        RECEIPT_TOKEN_ID,
+        shares,
+    )
+}
+
+/// Receive hook for receipt tokens transferred back to this contract. Burns
+/// them and pays their share of the liquid pool, principal plus rewards, to
+/// the previous holder, identified like in `stake_liquid`.
+#[receive(
+    contract = "gona_stake",
+    name = "unstake_liquid",
+    error = "StakingError",
+    parameter = "OnReceivingCis2Params<ReceiptTokenId,TokenAmountU64>",
+    enable_logger,
+    mutable
+)]
+fn unstake_liquid(
+    ctx: &ReceiveContext,
+    host: &mut Host<State>,
+    logger: &mut Logger,
+) -> ReceiveResult<()> {
+    let parameter: OnReceivingCis2Params<ReceiptTokenId, TokenAmountU64> =
+        ctx.parameter_cursor().get()?;
+    let self_address = Address::Contract(ctx.self_address());
+    let shares = parameter.amount;
+
+    // Only receipt tokens this contract has just received can be redeemed.
+    ensure_eq!(
+        ctx.sender(),
+        self_address,
+        StakingError::SenderContractAddressIsNotAllowedToStake.into()
+    );
+    ensure_eq!(
+        parameter.token_id,
+        RECEIPT_TOKEN_ID,
+        StakingError::InvalidTokenId.into()
+    );
+    ensure!(shares.0 > 0, StakingError::InsufficientFunds.into());
+    let (staker, _) = staker_from_transfer(parameter.from, &parameter.data)?;
+    let smart_wallet = staker_smart_wallet(host.state(), &staker, parameter.from)?;
+
+    let state = host.state_mut();
+    let reward_volume = state.reward_volume;
+    state.burn_receipts(&self_address, shares)?;
+    state.accrue_liquid_rewards(ctx.metadata().slot_time());
+    let amount = TokenAmountU64(state.withdraw_liquid(shares.0));
+    state.untrack_stake(&staker, amount.0);
+
+    transfer_to_staker(host, ctx.self_address(), &staker, smart_wallet, amount)?;
+
+    logger.log(&StakingEvent::Burn(BurnEvent {
+        token_id: RECEIPT_TOKEN_ID,
+        amount: shares,
+        owner: self_address,
+    }))?;
+    logger.log(&StakingEvent::LiquidUnstaked {
+        staker,
+        shares,
+        amount,
+    })?;
+    log_reward_volume(logger, host.state(), reward_volume)
+}
+
+/// Returns the liquid pool with the rewards it has earned up to now.
+#[receive(
+    contract = "gona_stake",
+    name = "view_liquid_pool",
+    return_value = "LiquidPool"
+)]
+fn view_liquid_pool(ctx: &ReceiveContext, host: &Host<State>) -> ReceiveResult<LiquidPool> {
+    let state = host.state();
+    let (rewards, _) = state.liquid_rewards_due(ctx.metadata().slot_time());
+    Ok(LiquidPool {
+        total_assets: state.liquid_pool.total_assets + rewards,
+        ..state.liquid_pool.clone()
+    })
+}
+
+/// CIS-2 transfer of receipt and position tokens. Transferring receipt tokens
+/// to the `unstake_liquid` hook of this contract redeems them, transferring a
+/// position hands it over with its principal and rewards.
+#[receive(
+    contract = "gona_stake",
+    name = "transfer",
+    parameter = "TransferParameter",
+    error = "StakingError",
+    enable_logger,
+    mutable
+)]
+fn transfer(
+    ctx: &ReceiveContext,
+    host: &mut Host<State>,
+    logger: &mut Logger,
+) -> ReceiveResult<()> {
+    let TransferParams(transfers): TransferParameter = ctx.parameter_cursor().get()?;
+    let sender = ctx.sender();
+    for Transfer {
+        token_id,
+        amount,
+        from,
+        to,
+        data,
+    } in transfers
+    {
+        let state = host.state_mut();
+        ensure!(
+            from == sender || state.operators.contains(&(from, sender)),
+            StakingError::SenderIsNotOwner.into()
+        );
+        let to_address = to.address();
+        let transferred = match token_position(&token_id) {
+            Some(position) => state
+                .transfer_position(position, amount, &from, &to, &data)?
+                .map(|owner| (position, owner)),
+            None => {
+                state.transfer_receipts(
+                    ctx.self_address(),
+                    &token_id,
+                    amount,
+                    &from,
+                    &to,
+                    &data,
+                )?;
+                None
+            }
+        };
+
+        logger.log(&StakingEvent::Transfer(TransferEvent {
+            token_id: token_id.clone(),
+            amount,
+            from,
+            to: to_address,
+        }))?;
+        if let Some((position, owner)) = transferred {
+            logger.log(&StakingEvent::PositionTransferred { position, owner })?;
+        }
+
+        if let Receiver::Contract(address, entrypoint) = to {
+            let parameter = OnReceivingCis2Params {
+                token_id,
+                amount,
+                from,
+                data,
+            };
+            host.invoke_contract(
+                &address,
+                &parameter,
+                entrypoint.as_entrypoint_name(),
+                Amount::zero(),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+#[receive(
+    contract = "gona_stake",
+    name = "updateOperator",
+    parameter = "UpdateOperatorParams",
+    error = "StakingError",
+    enable_logger,
+    mutable
+)]
+fn update_operator(
+    ctx: &ReceiveContext,
+    host: &mut Host<State>,
+    logger: &mut Logger,
+) -> ReceiveResult<()> {
+    let UpdateOperatorParams(params) = ctx.parameter_cursor().get()?;
+    let sender = ctx.sender();
+    let state = host.state_mut();
+    for param in params {
+        state.update_operator(param.update, sender, param.operator);
+        logger.log(&StakingEvent::UpdateOperator(UpdateOperatorEvent {
+            update: param.update,
+            owner: sender,
+            operator: param.operator,
+        }))?;
+    }
+    Ok(())
+}
+
+#[receive(
+    contract = "gona_stake",
+    name = "balanceOf",
+    parameter = "ReceiptBalanceOfQueryParams",
+    return_value = "ReceiptBalanceOfQueryResponse",
+    error = "StakingError"
+)]
+fn balance_of(
+    ctx: &ReceiveContext,
+    host: &Host<State>,
+) -> ReceiveResult<ReceiptBalanceOfQueryResponse> {
+    let params: ReceiptBalanceOfQueryParams = ctx.parameter_cursor().get()?;
+    let mut balances = Vec::with_capacity(params.queries.len());
+    for query in params.queries {
+        balances.push(
+            host.state()
+                .token_balance(&query.token_id, &query.address)?,
+        );
+    }
+    Ok(BalanceOfQueryResponse::from(balances))
+}
+
+#[receive(
+    contract = "gona_stake",
+    name = "operatorOf",
+    parameter = "OperatorOfQueryParams",
+    return_value = "OperatorOfQueryResponse",
+    error = "StakingError"
+)]
+fn operator_of(ctx: &ReceiveContext, host: &Host<State>) -> ReceiveResult<OperatorOfQueryResponse> {
+    let params: OperatorOfQueryParams = ctx.parameter_cursor().get()?;
+    let results: Vec<bool> = params
+        .queries
+        .iter()
+        .map(|query| {
+            host.state()
+                .operators
+                .contains(&(query.owner, query.address))
+        })
+        .collect();
+    Ok(OperatorOfQueryResponse::from(results))
+}
+
+#[receive(
+    contract = "gona_stake",
+    name = "tokenMetadata",
+    parameter = "ReceiptTokenMetadataQueryParams",
+    return_value = "TokenMetadataQueryResponse",
+    error = "StakingError"
+)]
+fn token_metadata(
+    ctx: &ReceiveContext,
+    host: &Host<State>,
+) -> ReceiveResult<TokenMetadataQueryResponse> {
+    let params: ReceiptTokenMetadataQueryParams = ctx.parameter_cursor().get()?;
+    let mut urls = Vec::with_capacity(params.queries.len());
+    for token_id in params.queries {
+        let exists = match token_position(&token_id) {
+            Some(position) => host.state().stake_entries.get(&position).is_some(),
+            None => token_id == RECEIPT_TOKEN_ID,
+        };
+        ensure!(exists, StakingError::InvalidTokenId.into());
+        urls.push(host.state().metadata_url.clone());
+    }
+    Ok(TokenMetadataQueryResponse::from(urls))
+}
+
+#[receive(
+    contract = "gona_stake",
+    name = "set_metadata_url",
+    parameter = "MetadataUrl",
+    error = "StakingError",
+    enable_logger,
+    mutable
+)]
+fn set_metadata_url(
+    ctx: &ReceiveContext,
+    host: &mut Host<State>,
+    logger: &mut Logger,
+) -> ReceiveResult<()> {
+    let metadata_url: MetadataUrl = ctx.parameter_cursor().get()?;
+    ensure_eq!(
+        ctx.sender(),
+        host.state.admin,
+        StakingError::SenderIsNotAdmin.into()
+    );
+    host.state_mut().metadata_url = metadata_url.clone();
+    logger.log(&StakingEvent::TokenMetadata(TokenMetadataEvent {
+        token_id: RECEIPT_TOKEN_ID,
+        metadata_url,
+    }))?;
     Ok(())
 }
@@ -0,0 +1,235 @@
+//! Rebuilds the state of the staking contract off-chain from its events.
+//!
+//! A [`Mirror`] starts from the parameter the contract was initialized with
+//! and is fed every [`StakingEvent`] the contract logged, in order, along with
+//! the time of the block it was logged in. [`Mirror::check`] then compares the
+//! result against `get_stake_info` and `view_reward_volume` of the contract.
+use crate::*;
+use concordium_std::collections::BTreeMap;
+
+/// A position as far as its events tell.
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub struct MirroredPosition {
+    pub owner: Staker,
+    pub amount: u64,
+    pub pending_rewards: u64,
+    pub auto_compound: bool,
+    pub referrer: Option<Staker>,
+}
+
+impl From<&StakeEntry> for MirroredPosition {
+    fn from(entry: &StakeEntry) -> Self {
+        MirroredPosition {
+            owner: entry.owner,
+            amount: entry.amount.0,
+            pending_rewards: entry.pending_rewards,
+            auto_compound: entry.auto_compound,
+            referrer: entry.referrer,
+        }
+    }
+}
+
+/// A difference between the mirror and the contract found by
+/// [`Mirror::check`].
+#[derive(PartialEq, Eq, Debug)]
+pub enum Inconsistency {
+    /// `None` on either side means the position does not exist there.
+    Position {
+        position: PositionId,
+        mirrored: Option<MirroredPosition>,
+        on_chain: Option<MirroredPosition>,
+    },
+    RewardVolume {
+        mirrored: u64,
+        on_chain: u64,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct Mirror {
+    pub positions: BTreeMap<PositionId, MirroredPosition>,
+    /// The principal of every staker across its positions.
+    pub balances: BTreeMap<Staker, u64>,
+    pub reward_volume: u64,
+    /// Every weight the contract has had, since the time it was set.
+    pub weight_history: Vec<(Timestamp, u32)>,
+    pub paused: bool,
+    pub admin: Address,
+    /// The highest position opened so far.
+    pub last_position: PositionId,
+}
+
+impl Mirror {
+    /// The state of a contract initialized with `param` at `time`.
+    pub fn new(param: &InitParam, time: Timestamp) -> Self {
+        Mirror {
+            positions: BTreeMap::new(),
+            balances: BTreeMap::new(),
+            reward_volume: 0,
+            weight_history: vec![(time, param.weight)],
+            paused: false,
+            admin: param.admin,
+            last_position: 0,
+        }
+    }
+
+    /// The weight rewards are currently calculated with.
+    pub fn weight(&self) -> u32 {
+        self.weight_history.last().map_or(0, |(_, weight)| *weight)
+    }
+
+    /// Applies the events of a contract in the order they were logged.
+    pub fn apply_all<'a>(
+        &mut self,
+        events: impl IntoIterator<Item = (Timestamp, &'a StakingEvent)>,
+    ) {
+        for (time, event) in events {
+            self.apply(time, event);
+        }
+    }
+
+    /// Applies an event logged by the contract in a block at `time`. Events
+    /// that do not change the mirrored state are ignored.
+    pub fn apply(&mut self, time: Timestamp, event: &StakingEvent) {
+        match event {
+            StakingEvent::Staked {
+                position,
+                staker,
+                amount,
+                ..
+            } => {
+                self.positions.insert(
+                    *position,
+                    MirroredPosition {
+                        owner: *staker,
+                        amount: amount.0,
+                        pending_rewards: 0,
+                        auto_compound: false,
+                        referrer: None,
+                    },
+                );
+                self.add_balance(*staker, amount.0);
+                self.last_position = self.last_position.max(*position);
+            }
+            StakingEvent::Referred {
+                position, referrer, ..
+            } => {
+                if let Some(entry) = self.positions.get_mut(position) {
+                    entry.referrer = Some(*referrer);
+                }
+            }
+            StakingEvent::Unstaking {
+                position,
+                remaining,
+                ..
+            } => {
+                if let Some(entry) = self.positions.get_mut(position) {
+                    let (owner, removed) = (entry.owner, entry.amount - remaining.0);
+                    entry.amount = remaining.0;
+                    self.remove_balance(owner, removed);
+                }
+            }
+            StakingEvent::PartialRewardPayout { position, owed, .. } => {
+                if let Some(entry) = self.positions.get_mut(position) {
+                    entry.pending_rewards += owed.0;
+                }
+            }
+            StakingEvent::RewardsClaimed {
+                position,
+                remaining,
+                ..
+            } => {
+                if let Some(entry) = self.positions.get_mut(position) {
+                    entry.pending_rewards = remaining.0;
+                }
+            }
+            StakingEvent::Compounded {
+                position,
+                rewards,
+                amount,
+                ..
+            } => {
+                if let Some(entry) = self.positions.get_mut(position) {
+                    entry.amount = amount.0;
+                    let owner = entry.owner;
+                    self.add_balance(owner, rewards.0);
+                }
+            }
+            StakingEvent::EmergencyWithdraw { position, .. } => self.close_position(*position),
+            StakingEvent::Burn(BurnEvent { token_id, .. }) => {
+                if let Some(position) = token_position(token_id) {
+                    self.close_position(position);
+                }
+            }
+            StakingEvent::PositionTransferred { position, owner } => {
+                if let Some(entry) = self.positions.get_mut(position) {
+                    let (previous, amount) = (entry.owner, entry.amount);
+                    entry.owner = *owner;
+                    self.remove_balance(previous, amount);
+                    self.add_balance(*owner, amount);
+                }
+            }
+            StakingEvent::AutoCompoundSet { position, enabled } => {
+                if let Some(entry) = self.positions.get_mut(position) {
+                    entry.auto_compound = *enabled;
+                }
+            }
+            StakingEvent::RewardVolumeChanged { reward_volume } => {
+                self.reward_volume = reward_volume.0
+            }
+            StakingEvent::WeightChanged { weight } => self.weight_history.push((time, *weight)),
+            StakingEvent::PauseChanged { paused } => self.paused = *paused,
+            StakingEvent::AdminChanged { admin, .. } => self.admin = *admin,
+            _ => (),
+        }
+    }
+
+    /// Compares the mirror with the contract, querying `get_stake_info` for
+    /// every position opened so far. Returns every difference found.
+    pub fn check<E>(
+        &self,
+        mut get_stake_info: impl FnMut(PositionId) -> Result<StakeQuery, E>,
+        reward_volume: u64,
+    ) -> Result<Vec<Inconsistency>, E> {
+        let mut inconsistencies = Vec::new();
+        for position in 1..=self.last_position {
+            let on_chain = get_stake_info(position)?
+                .as_ref()
+                .map(MirroredPosition::from);
+            let mirrored = self.positions.get(&position).cloned();
+            if mirrored != on_chain {
+                inconsistencies.push(Inconsistency::Position {
+                    position,
+                    mirrored,
+                    on_chain,
+                });
+            }
+        }
+        if self.reward_volume != reward_volume {
+            inconsistencies.push(Inconsistency::RewardVolume {
+                mirrored: self.reward_volume,
+                on_chain: reward_volume,
+            });
+        }
+        Ok(inconsistencies)
+    }
+
+    fn close_position(&mut self, position: PositionId) {
+        if let Some(entry) = self.positions.remove(&position) {
+            self.remove_balance(entry.owner, entry.amount);
+        }
+    }
+
+    fn add_balance(&mut self, staker: Staker, amount: u64) {
+        *self.balances.entry(staker).or_insert(0) += amount;
+    }
+
+    fn remove_balance(&mut self, staker: Staker, amount: u64) {
+        if let Some(balance) = self.balances.get_mut(&staker) {
+            *balance = balance.saturating_sub(amount);
+            if *balance == 0 {
+                self.balances.remove(&staker);
+            }
+        }
+    }
+}
@@ -1,27 +1,66 @@
 use crate::*;
 
 #[derive(Serialize, SchemaType)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
 pub struct InitParam {
     /// The contract address of the token.
     pub token_address: ContractAddress,
     /// The weight at which rewards are calculated, should be in percentage
+    /// per day. Rewards accrue continuously at `weight * 365` per year.
     pub weight: u32,
     /// the decimals of the token contract,
     pub decimals: u8,
     pub admin: Address,
-    pub smart_wallet: ContractAddress,
+    /// The smart wallet contracts public keys are allowed to stake from.
+    pub smart_wallets: Vec<ContractAddress>,
+    pub limits: StakeLimits,
+}
+
+/// Bounds on the amounts staked, in the smallest unit of the token.
+#[derive(Serialize, SchemaType, PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
+pub struct StakeLimits {
+    /// The least a single stake can be.
+    pub min_stake: u64,
+    /// Positions left with less than this by an unstake are closed.
+    pub dust_threshold: u64,
+    /// The most a staker can have staked across its positions.
+    pub max_per_staker: Option<u64>,
+    /// The most that can be staked in positions and the liquid pool together.
+    pub pool_capacity: Option<u64>,
+}
+
+impl StakeLimits {
+    /// Whether the limits leave room for a stake of `min_stake`.
+    pub fn is_valid(&self) -> bool {
+        self.max_per_staker.is_none_or(|max| max >= self.min_stake)
+            && self
+                .pool_capacity
+                .is_none_or(|capacity| capacity >= self.min_stake)
+    }
 }
 
 pub type ContractTokenId = TokenIdUnit;
 pub type ContractTokenAmount = TokenAmountU64;
 
+/// The token id type of the receipt and position tokens this contract issues.
+pub type ReceiptTokenId = TokenIdVec;
+/// Identifies a stake position, which is also a CIS-2 NFT of this contract.
+pub type PositionId = u64;
+pub type TransferParameter = TransferParams<ReceiptTokenId, TokenAmountU64>;
+pub type ReceiptBalanceOfQueryParams = BalanceOfQueryParams<ReceiptTokenId>;
+pub type ReceiptBalanceOfQueryResponse = BalanceOfQueryResponse<TokenAmountU64>;
+pub type ReceiptTokenMetadataQueryParams = TokenMetadataQueryParams<ReceiptTokenId>;
+
 #[derive(Serialize, SchemaType, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
 pub struct Unbounding {
     amount: Amount,
     unlock_time: Timestamp,
 }
 
 #[derive(Serialize, SchemaType, Clone)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
 pub struct StakerInfo {
     staked_amount: Amount,
     last_reward_timestamp: Timestamp,
@@ -30,26 +69,155 @@ pub struct StakerInfo {
     slashed: bool, // Track if staker has been slashed
 }
 
+/// The identity a stake is recorded under.
+#[derive(Serialize, SchemaType, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
+pub enum Staker {
+    /// A public key staking through the smart wallet.
+    PublicKey(PublicKeyEd25519),
+    /// An address staking directly from its own balance.
+    Address(Address),
+}
+
 #[derive(Serialize, SchemaType, PartialEq, Eq, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
 pub struct StakeEntry {
+    /// The owner of the position NFT, who the position pays out to.
+    pub owner: Staker,
     pub amount: TokenAmountU64,
     pub time_of_stake: Timestamp,
     pub token_id: TokenIdUnit,
+    /// Rewards owed to the staker that the pool could not pay out yet.
+    pub pending_rewards: u64,
+    /// The smart wallet holding the position of a public key, payouts are
+    /// sent back to it.
+    pub smart_wallet: Option<ContractAddress>,
+    /// Whether `compound` may roll the rewards of the position into it.
+    pub auto_compound: bool,
+    /// For positions opened once epochs were configured, the epoch their
+    /// rewards are counted from. Other positions earn by `weight` per day.
+    pub reward_epoch: Option<u64>,
+    /// Who referred the stake, earning a share of the rewards it pays out.
+    pub referrer: Option<Staker>,
+}
+
+/// The referrals of a referrer and the rewards they earned it.
+#[derive(Serialize, SchemaType, PartialEq, Eq, Clone, Default, Debug)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
+pub struct ReferralStats {
+    /// The positions staked with the referrer.
+    pub referees: u64,
+    pub earned: u64,
+    /// Earned and not claimed yet.
+    pub claimable: u64,
+}
+
+/// Reward per staked token is tracked scaled up by this factor.
+pub const REWARD_SCALE: u128 = 1_000_000_000_000;
+
+/// Epoch based reward distribution: every epoch, `budget` is shared among the
+/// positions eligible in it in proportion to their stake.
+#[derive(Serialize, SchemaType, PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
+pub struct Epochs {
+    pub length: Duration,
+    pub budget: u64,
+    pub current: u64,
+    pub current_start: Timestamp,
+    /// The stake earning rewards in the current epoch.
+    pub eligible_stake: u64,
+    /// The stake added in the current epoch, eligible from the next one.
+    pub pending_stake: u64,
+    /// Rewards per staked token up to the start of the current epoch, scaled
+    /// by `REWARD_SCALE`.
+    pub reward_per_stake: u128,
+}
+
+impl Epochs {
+    /// The reward per staked token the current epoch pays out.
+    fn epoch_reward_per_stake(&self) -> u128 {
+        if self.eligible_stake == 0 {
+            return 0;
+        }
+        u128::from(self.budget) * REWARD_SCALE / u128::from(self.eligible_stake)
+    }
+
+    /// Advances to the epoch `now` falls in. Returns the first epoch rolled
+    /// into and the reward per stake at its start, if any epoch ended.
+    pub fn roll(&mut self, now: Timestamp) -> Option<(u64, u128)> {
+        let elapsed = now
+            .duration_since(self.current_start)
+            .map_or(0, |duration| duration.millis() / self.length.millis());
+        if elapsed == 0 {
+            return None;
+        }
+        self.reward_per_stake += self.epoch_reward_per_stake();
+        self.eligible_stake += self.pending_stake;
+        self.pending_stake = 0;
+        let checkpoint = (self.current + 1, self.reward_per_stake);
+        // the epochs after that all share out the budget over the same stake
+        self.reward_per_stake += self.epoch_reward_per_stake() * u128::from(elapsed - 1);
+        self.current += elapsed;
+        self.current_start = self
+            .current_start
+            .checked_add(Duration::from_millis(self.length.millis() * elapsed))
+            .unwrap_or(now);
+        Some(checkpoint)
+    }
+}
+
+/// The epoch settings set through `update_settings`.
+#[derive(Serialize, SchemaType, PartialEq, Eq, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
+pub struct EpochConfig {
+    pub length: Duration,
+    /// The rewards shared out every epoch.
+    pub budget: u64,
+}
+
+/// The return value of `view_epoch`.
+#[derive(Serialize, SchemaType, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
+pub struct EpochInfo {
+    pub current: u64,
+    pub next_epoch_start: Timestamp,
+    pub budget: u64,
+}
+
+/// The staked tokens backing the liquid staking receipt token.
+#[derive(Serialize, SchemaType, PartialEq, Eq, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
+pub struct LiquidPool {
+    /// Principal staked into the pool plus the rewards it has accrued.
+    pub total_assets: u64,
+    /// The receipt tokens in circulation.
+    pub total_shares: u64,
+    /// Rewards have been accrued into the pool up to this time.
+    pub last_accrual: Timestamp,
 }
 
 #[derive(Debug, Serial, Deserial, PartialEq, Eq, SchemaType)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
 #[concordium(repr(u8))]
 pub enum StakingEvent {
     #[concordium(tag = 246)]
     Staked {
-        staker: PublicKeyEd25519,
+        position: PositionId,
+        staker: Staker,
         amount: TokenAmountU64,
         time: Timestamp,
     },
     #[concordium(tag = 245)]
     Unstaking {
-        staker: PublicKeyEd25519,
+        position: PositionId,
+        staker: Staker,
         amount: TokenAmountU64,
+        /// The rewards paid out of the reward volume, fee included.
+        rewards: TokenAmountU64,
+        /// The protocol fee taken from the rewards paid out.
+        fee: TokenAmountU64,
+        /// The principal left in the position.
+        remaining: TokenAmountU64,
         time: Timestamp,
     },
     #[concordium(tag = 244)]
@@ -62,9 +230,154 @@ pub enum StakingEvent {
         sender: Address,
         amount: TokenAmountU64,
     },
+    #[concordium(tag = 242)]
+    PartialRewardPayout {
+        position: PositionId,
+        staker: Staker,
+        paid: TokenAmountU64,
+        owed: TokenAmountU64,
+    },
+    #[concordium(tag = 241)]
+    RewardsClaimed {
+        position: PositionId,
+        staker: Staker,
+        amount: TokenAmountU64,
+        fee: TokenAmountU64,
+        remaining: TokenAmountU64,
+    },
+    /// Emitted when a `permit` consumes the nonce of a public key.
+    #[concordium(tag = 250)]
+    Nonce {
+        signer: PublicKeyEd25519,
+        nonce: u64,
+    },
+    #[concordium(tag = 240)]
+    EmergencyWithdraw {
+        position: PositionId,
+        staker: Staker,
+        amount: TokenAmountU64,
+        forfeited_rewards: TokenAmountU64,
+        time: Timestamp,
+    },
+    /// The CIS-2 events of the receipt and position tokens.
+    #[concordium(tag = 255)]
+    Transfer(
+        #[cfg_attr(feature = "serde", serde(with = "cis2_serde::TransferEventDef"))]
+        TransferEvent<ReceiptTokenId, TokenAmountU64>,
+    ),
+    #[concordium(tag = 254)]
+    Mint(
+        #[cfg_attr(feature = "serde", serde(with = "cis2_serde::MintEventDef"))]
+        MintEvent<ReceiptTokenId, TokenAmountU64>,
+    ),
+    #[concordium(tag = 253)]
+    Burn(
+        #[cfg_attr(feature = "serde", serde(with = "cis2_serde::BurnEventDef"))]
+        BurnEvent<ReceiptTokenId, TokenAmountU64>,
+    ),
+    #[concordium(tag = 252)]
+    UpdateOperator(
+        #[cfg_attr(feature = "serde", serde(with = "cis2_serde::UpdateOperatorEventDef"))]
+        UpdateOperatorEvent,
+    ),
+    #[concordium(tag = 251)]
+    TokenMetadata(
+        #[cfg_attr(feature = "serde", serde(with = "cis2_serde::TokenMetadataEventDef"))]
+        TokenMetadataEvent<ReceiptTokenId>,
+    ),
+    #[concordium(tag = 239)]
+    LiquidStaked {
+        staker: Staker,
+        amount: TokenAmountU64,
+        shares: TokenAmountU64,
+    },
+    #[concordium(tag = 238)]
+    LiquidUnstaked {
+        staker: Staker,
+        shares: TokenAmountU64,
+        amount: TokenAmountU64,
+    },
+    #[concordium(tag = 237)]
+    OperatorUpdated {
+        owner: Staker,
+        operator: Address,
+        update: OperatorUpdate,
+    },
+    #[concordium(tag = 236)]
+    Compounded {
+        position: PositionId,
+        staker: Staker,
+        rewards: TokenAmountU64,
+        amount: TokenAmountU64,
+    },
+    #[concordium(tag = 235)]
+    KeeperRewarded {
+        keeper: AccountAddress,
+        bounty: TokenAmountU64,
+    },
+    /// The penalty taken by an early unstake, sent to `treasury` or back into
+    /// the reward volume if it is `None`.
+    #[concordium(tag = 234)]
+    EarlyExitPenalty {
+        position: PositionId,
+        staker: Staker,
+        amount: TokenAmountU64,
+        treasury: Option<Address>,
+    },
+    #[concordium(tag = 233)]
+    FeesWithdrawn { to: Address, amount: TokenAmountU64 },
+    #[concordium(tag = 232)]
+    Referred {
+        position: PositionId,
+        staker: Staker,
+        referrer: Staker,
+    },
+    /// A referrer was credited its share of the rewards paid on a position.
+    #[concordium(tag = 231)]
+    ReferralReward {
+        position: PositionId,
+        referrer: Staker,
+        amount: TokenAmountU64,
+    },
+    #[concordium(tag = 230)]
+    ReferralRewardsClaimed {
+        referrer: Staker,
+        amount: TokenAmountU64,
+    },
+    /// The reward volume at the end of a call that changed it.
+    #[concordium(tag = 229)]
+    RewardVolumeChanged { reward_volume: TokenAmountU64 },
+    #[concordium(tag = 228)]
+    WeightChanged { weight: u32 },
+    #[concordium(tag = 227)]
+    PauseChanged { paused: bool },
+    #[concordium(tag = 226)]
+    AdminChanged { previous: Address, admin: Address },
+    /// Any other update through `update_settings`.
+    #[concordium(tag = 225)]
+    SettingUpdated(SettingUpdate),
+    #[concordium(tag = 224)]
+    SmartWalletUpdated {
+        smart_wallet: ContractAddress,
+        trusted: bool,
+    },
+    #[concordium(tag = 223)]
+    PayoutAllowlistUpdated { address: Address, allowed: bool },
+    #[concordium(tag = 222)]
+    StakerListUpdated {
+        list: StakerList,
+        staker: Staker,
+        listed: bool,
+    },
+    #[concordium(tag = 221)]
+    AutoCompoundSet { position: PositionId, enabled: bool },
+    /// The new owner of a position whose NFT was transferred.
+    #[concordium(tag = 220)]
+    PositionTransferred { position: PositionId, owner: Staker },
 }
 
 #[derive(Debug, PartialEq, Eq, Clone, Reject, Serialize, SchemaType)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
 pub enum StakingError {
     StakingNotFound,
     InsufficientFunds,
@@ -87,9 +400,36 @@ pub enum StakingError {
     SignatureVerficationFailed,
     CouldNotParseAdditionalData,
     Overflow,
+    NoPendingRewards,
+    RewardPoolDepleted,
+    EmergencyModeDisabled,
+    PayoutReceiverRequired,
+    ReceiverNotAllowed,
+    MissingSignature,
+    NonceMismatch,
+    UntrustedSmartWallet,
+    WrongContract,
+    WrongEntryPoint,
+    InvalidTokenId,
+    EpochsNotConfigured,
+    InvalidEpochLength,
+    InvalidAccrualGranularity,
+    StakeAboveStakerMaximum,
+    PoolCapacityExceeded,
+    InvalidStakeLimits,
+    InvalidPenaltySchedule,
+    InvalidFee,
+    SenderIsNotFeeCollector,
+    InvalidReferralRate,
+    SelfReferral,
+    NotAllowlisted,
+    StakerDenylisted,
+    InvalidKeeperBounty,
+    InvalidReferrer,
 }
 
 #[derive(Serialize, SchemaType)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
 pub struct RewardResult {
     pub days: u64,
     pub rewards: u64,
@@ -99,9 +439,392 @@ pub struct RewardResult {
 pub type StakeQuery = Option<StakeEntry>;
 
 #[derive(Serialize, SchemaType)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
 pub struct UnstakeParam {
     pub amount: TokenAmountU64,
+    pub position: PositionId,
+    /// Where to send the tokens, defaults to the owner of the position.
+    pub payout: Option<Payout>,
+    /// Required from public key stakers that set a custom `payout`.
+    pub signature: Option<PayoutSignature>,
+}
+
+/// A penalty on unstaking that decays linearly from `max_bps` basis points at
+/// `time_of_stake` to nothing after `duration`.
+#[derive(Serialize, SchemaType, PartialEq, Eq, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
+pub struct PenaltySchedule {
+    pub max_bps: u16,
+    pub duration: Duration,
+    pub base: PenaltyBase,
+    pub destination: PenaltyDestination,
+}
+
+/// What the early exit penalty is taken from.
+#[derive(Serialize, SchemaType, PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
+pub enum PenaltyBase {
+    /// The principal being unstaked.
+    Principal,
+    /// The rewards paid on the principal being unstaked.
+    Rewards,
+}
+
+/// Where the early exit penalty goes.
+#[derive(Serialize, SchemaType, PartialEq, Eq, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
+pub enum PenaltyDestination {
+    /// Back into the reward volume for the remaining stakers.
+    RewardVolume,
+    Treasury(Payout),
+}
+
+/// An update of the settings of the contract by the admin.
+#[derive(Serialize, SchemaType, PartialEq, Eq, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
+pub enum SettingUpdate {
+    /// Allows `emergency_withdraw` while enabled.
+    Emergency(bool),
+    /// Restricts custom payout receivers to the payout allowlist while enabled.
+    PayoutAllowlistEnabled(bool),
+    KeeperBounty(KeeperBounty),
+    Epochs(EpochConfig),
+    AccrualGranularity(Duration),
+    StakeLimits(StakeLimits),
+    PenaltySchedule(Option<PenaltySchedule>),
+    /// The protocol fee on rewards, in basis points.
+    ProtocolFee(u16),
+    FeeCollector(Address),
+    /// The share of rewards paid to referrers, in basis points.
+    ReferralRate(u16),
+    /// Only allowlisted stakers may stake while enabled.
+    StakerGating(bool),
+    /// Hands the admin role over to another address.
+    Admin(Address),
+}
+
+/// The return value of `view_settings`.
+#[derive(Serialize, SchemaType, Debug)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
+pub struct Settings {
+    /// The daily reward rate, accruing at `weight * 365` per year.
+    pub weight: u32,
+    pub paused: bool,
+    pub emergency: bool,
+    pub payout_allowlist_enabled: bool,
+    pub keeper_bounty: KeeperBounty,
+    pub accrual_granularity: Duration,
+    pub limits: StakeLimits,
+    pub penalty_schedule: Option<PenaltySchedule>,
+    pub protocol_fee: ProtocolFee,
+    pub referral_bps: u16,
+    pub staker_gating: bool,
+}
+
+/// The return value of `view_staked`.
+#[derive(Serialize, SchemaType, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
+pub struct StakedTotals {
+    /// The principal of the staker across its positions and in the liquid
+    /// pool.
+    pub staker: u64,
+    /// The principal staked in positions and the liquid pool together.
+    pub total: u64,
+}
+
+/// The protocol fee taken from reward payouts.
+#[derive(Serialize, SchemaType, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
+pub struct ProtocolFee {
+    pub fee_bps: u16,
+    /// Fees taken so far and not yet withdrawn.
+    pub fees_collected: u64,
+    /// The address allowed to withdraw the fees.
+    pub fee_collector: Address,
+}
+
+#[derive(Serialize, SchemaType)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
+pub struct WithdrawFeesParam {
+    pub amount: TokenAmountU64,
+    pub payout: Payout,
+}
+
+#[derive(Serialize, SchemaType)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
+pub struct QuoteUnstakeParam {
+    pub position: PositionId,
+    pub amount: TokenAmountU64,
+}
+
+/// What unstaking `amount` of a position right now would pay out.
+#[derive(Serialize, SchemaType, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
+pub struct UnstakeQuote {
+    /// The rewards that would be paid, after any penalty on them.
+    pub rewards: u64,
+    /// The rewards the pool cannot pay yet, left pending on the position.
+    pub owed: u64,
+    pub penalty: u64,
+    /// The protocol fee taken from the rewards paid.
+    pub fee: u64,
+    /// The principal after any penalty on it plus the rewards paid, less the
+    /// protocol fee. A balance left below the dust threshold is paid as well.
+    pub payout: u64,
+}
+
+/// A receiver chosen by the staker for an unstake.
+#[derive(Serialize, SchemaType, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
+pub struct Payout {
+    pub to: Receiver,
+    /// Additional data for the receive hook of `to`.
+    pub data: AdditionalData,
+}
+
+// `Receiver` and `AdditionalData` do not implement `PartialEq`, so payouts are
+// compared by their serialization.
+impl PartialEq for Payout {
+    fn eq(&self, other: &Self) -> bool {
+        to_bytes(self) == to_bytes(other)
+    }
+}
+
+impl Eq for Payout {}
+
+#[derive(Serialize, SchemaType, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
+pub struct PayoutSignature {
+    pub signature: SignatureEd25519,
+    /// Has to match the next nonce of the public key.
+    pub nonce: u64,
+    pub expiry_time: Timestamp,
+}
+
+/// The message a public key staker signs to unstake to a custom receiver.
+#[derive(Serialize, SchemaType, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
+pub struct PayoutMessage {
+    pub contract_address: ContractAddress,
     pub staker: PublicKeyEd25519,
+    pub position: PositionId,
+    pub amount: TokenAmountU64,
+    pub payout: Payout,
+    pub nonce: u64,
+    pub expiry_time: Timestamp,
 }
 
+#[derive(Serialize, SchemaType)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
+pub struct SetAutoCompoundParam {
+    pub position: PositionId,
+    pub enabled: bool,
+}
+
+#[derive(Serialize, SchemaType)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
+pub struct CompoundParams {
+    /// Positions without auto compounding are skipped.
+    #[concordium(size_length = 2)]
+    pub positions: Vec<PositionId>,
+}
+
+/// The bounty paid out of the reward volume to callers of maintenance
+/// entrypoints like `compound`.
+#[derive(Serialize, SchemaType, PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
+pub struct KeeperBounty {
+    /// Paid for every item a call processes.
+    pub per_item: u64,
+    /// The most a single call is paid.
+    pub max_per_call: u64,
+    /// The most a call is paid as a share of the rewards it compounded, in
+    /// basis points.
+    pub max_share_bps: u16,
+}
+
+#[derive(Serialize, SchemaType)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
+pub struct UpdateSmartWalletsParams {
+    pub add: Vec<ContractAddress>,
+    pub remove: Vec<ContractAddress>,
+}
+
+#[derive(Serialize, SchemaType)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
+pub struct UpdatePayoutAllowlistParams {
+    pub add: Vec<Address>,
+    pub remove: Vec<Address>,
+}
+
+/// The lists gating who may stake.
+#[derive(Serialize, SchemaType, PartialEq, Eq, Clone, Copy, Debug)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
+pub enum StakerList {
+    /// Only consulted while staker gating is enabled.
+    Allowlist,
+    Denylist,
+}
+
+#[derive(Serialize, SchemaType)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
+pub struct UpdateStakerListParams {
+    pub list: StakerList,
+    pub add: Vec<Staker>,
+    pub remove: Vec<Staker>,
+}
+
+/// The return value of `view_staker_access`.
+#[derive(Serialize, SchemaType, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
+pub struct StakerAccess {
+    pub allowlisted: bool,
+    pub denylisted: bool,
+    /// Whether the staker may open new stakes.
+    pub can_stake: bool,
+}
+
+/// The message a public key signs for `permit`.
+#[derive(Serialize, SchemaType, Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
+pub struct PermitMessage {
+    /// The address of the intended contract.
+    pub contract_address: ContractAddress,
+    /// A nonce to prevent replay attacks.
+    pub nonce: u64,
+    /// A timestamp to make the signature expire.
+    pub timestamp: Timestamp,
+    /// The entrypoint the signature is intended for.
+    pub entry_point: OwnedEntrypointName,
+    /// The serialized parameter for the entrypoint.
+    #[concordium(size_length = 2)]
+    pub payload: Vec<u8>,
+}
+
+/// The parameter of `permit`: a message signed by a public key.
+#[derive(Serialize, SchemaType)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
+pub struct PermitParam {
+    pub signature: SignatureEd25519,
+    pub signer: PublicKeyEd25519,
+    pub message: PermitMessage,
+}
+
+#[derive(Serialize, SchemaType)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
+pub struct SupportsPermitQueryParams {
+    /// The entrypoints to check for `permit` support.
+    #[concordium(size_length = 2)]
+    pub queries: Vec<OwnedEntrypointName>,
+}
+
+#[derive(Serialize, SchemaType)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
+pub struct StakerOperatorOfQuery {
+    pub owner: Staker,
+    /// The address to check for being an operator of `owner`.
+    pub address: Address,
+}
+
+#[derive(Serialize, SchemaType)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
+pub struct StakerOperatorOfQueryParams {
+    #[concordium(size_length = 2)]
+    pub queries: Vec<StakerOperatorOfQuery>,
+}
+
+#[derive(Serialize, SchemaType)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
+pub struct NonceOfQueryParams {
+    #[concordium(size_length = 2)]
+    pub queries: Vec<PublicKeyEd25519>,
+}
+
+#[derive(Serialize, SchemaType, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(SerdeSerialize, SerdeDeserialize))]
+#[concordium(transparent)]
+pub struct NonceOfQueryResponse(#[concordium(size_length = 2)] pub Vec<u64>);
+
 pub const TOKEN_ID: TokenIdUnit = TokenIdUnit();
+
+/// The liquid staking receipt token. Its id is empty, which serializes like
+/// `TokenIdUnit`, so smart wallets can hold it next to the staked token.
+pub const RECEIPT_TOKEN_ID: ReceiptTokenId = TokenIdVec(Vec::new());
+
+/// The token id of the NFT of a position: its id as 8 little endian bytes.
+pub fn position_token_id(position: PositionId) -> ReceiptTokenId {
+    TokenIdVec(position.to_le_bytes().to_vec())
+}
+
+/// The position a token id refers to, if it is a position NFT.
+pub fn token_position(token_id: &ReceiptTokenId) -> Option<PositionId> {
+    let bytes: [u8; 8] = token_id.0.as_slice().try_into().ok()?;
+    Some(PositionId::from_le_bytes(bytes))
+}
+
+/// The standard identifier of the staking interface of this contract.
+pub const STAKING_STANDARD_IDENTIFIER: StandardIdentifier<'static> =
+    StandardIdentifier::new_unchecked("GONA-STAKE");
+
+/// The standards this contract answers `supports` for. `permit` is signed by
+/// Ed25519 public keys rather than accounts, so CIS-3 is not among them.
+pub const SUPPORTED_STANDARDS: [StandardIdentifier<'static>; 3] = [
+    CIS0_STANDARD_IDENTIFIER,
+    CIS2_STANDARD_IDENTIFIER,
+    STAKING_STANDARD_IDENTIFIER,
+];
+
+/// Serde definitions of the CIS-2 events in [`StakingEvent`], which
+/// `concordium-cis2` does not derive serde for.
+#[cfg(feature = "serde")]
+mod cis2_serde {
+    use super::*;
+
+    #[derive(SerdeSerialize, SerdeDeserialize)]
+    #[serde(remote = "TransferEvent<ReceiptTokenId, TokenAmountU64>")]
+    pub struct TransferEventDef {
+        token_id: ReceiptTokenId,
+        amount: TokenAmountU64,
+        from: Address,
+        to: Address,
+    }
+
+    #[derive(SerdeSerialize, SerdeDeserialize)]
+    #[serde(remote = "MintEvent<ReceiptTokenId, TokenAmountU64>")]
+    pub struct MintEventDef {
+        token_id: ReceiptTokenId,
+        amount: TokenAmountU64,
+        owner: Address,
+    }
+
+    #[derive(SerdeSerialize, SerdeDeserialize)]
+    #[serde(remote = "BurnEvent<ReceiptTokenId, TokenAmountU64>")]
+    pub struct BurnEventDef {
+        token_id: ReceiptTokenId,
+        amount: TokenAmountU64,
+        owner: Address,
+    }
+
+    #[derive(SerdeSerialize, SerdeDeserialize)]
+    #[serde(remote = "UpdateOperatorEvent")]
+    pub struct UpdateOperatorEventDef {
+        update: OperatorUpdate,
+        owner: Address,
+        operator: Address,
+    }
+
+    #[derive(SerdeSerialize, SerdeDeserialize)]
+    #[serde(remote = "TokenMetadataEvent<ReceiptTokenId>")]
+    pub struct TokenMetadataEventDef {
+        token_id: ReceiptTokenId,
+        #[serde(with = "MetadataUrlDef")]
+        metadata_url: MetadataUrl,
+    }
+
+    #[derive(SerdeSerialize, SerdeDeserialize)]
+    #[serde(remote = "MetadataUrl")]
+    pub struct MetadataUrlDef {
+        url: String,
+        hash: Option<[u8; 32]>,
+    }
+}
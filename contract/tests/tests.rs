@@ -1,21 +1,23 @@
 //! Tests for the `smart_contract_wallet` contract.
 use concordium_cis2::*;
 use concordium_smart_contract_testing::*;
-use concordium_std::{Deserial, PublicKeyEd25519, SchemaType, Serial, Serialize, SignatureEd25519};
+use concordium_std::{
+    to_bytes, Deserial, PublicKeyEd25519, SchemaType, Serial, Serialize, SignatureEd25519,
+};
+use gona_stake::mirror::*;
 use gona_stake::types::*;
-use gona_stake::*;
 use primitive_types::*;
+#[cfg(feature = "serde")]
+use serde::{Deserialize as SerdeDeserialize, Serialize as SerdeSerialize};
 
 /// The tests accounts.
 const ALICE: AccountAddress = AccountAddress([0; 32]);
 const ALICE_ADDR: Address = Address::Account(ALICE);
 const BOB: AccountAddress = AccountAddress([1; 32]);
-//const BOB_ADDR: Address = Address::Account(BOB);
+const BOB_ADDR: Address = Address::Account(BOB);
 const CHARLIE: AccountAddress = AccountAddress([2; 32]);
 const CHARLIE_ADDR: Address = Address::Account(CHARLIE);
 
-const ALICE_PUBLIC_KEY: PublicKeyEd25519 = PublicKeyEd25519([7; 32]);
-const BOB_PUBLIC_KEY: PublicKeyEd25519 = PublicKeyEd25519([8; 32]);
 const SERVICE_FEE_RECIPIENT_KEY: PublicKeyEd25519 = PublicKeyEd25519([9; 32]);
 
 /// Initial balance of the accounts.
@@ -38,6 +40,15 @@ const WEIGHT: u32 = 8500;
 const DECIMALS: u8 = 6;
 /// days of stake: 20_u64 days
 const DAYS: u64 = 20;
+/// The stake limits the contract is initialized with: 0.001 of the token.
+const STAKE_LIMITS: StakeLimits = StakeLimits {
+    min_stake: 1000,
+    dust_threshold: 1000,
+    max_per_staker: None,
+    pool_capacity: None,
+};
+/// The position the first stake of a test is recorded under.
+const POSITION: PositionId = 1;
 
 const DUMMY_SIGNATURE: SignatureEd25519 = SignatureEd25519([
     68, 134, 96, 171, 184, 199, 1, 93, 76, 87, 144, 68, 55, 180, 93, 56, 107, 95, 127, 112, 24, 55,
@@ -168,6 +179,7 @@ fn test_stake_cis2_tokens() {
         cis2_token_contract_address,
         alice_public_key,
         gona_stake_address,
+        POOL_REWARD_AMOUNT,
     );
 
     let service_fee_amount: TokenAmountU256 = TokenAmountU256(0.into());
@@ -246,9 +258,9 @@ fn test_stake_cis2_tokens() {
         )
         .expect("Should be able to withdraw cis2 tokens");
 
-    let stake = get_stake_query(&mut chain, gona_stake_address, alice_public_key);
+    let stake = get_stake_query(&mut chain, gona_stake_address, POSITION);
     println!("{:?}", stake);
-    assert_eq!(stake.is_some(), true, "Stake did not return");
+    assert!(stake.is_some(), "Stake did not return");
 
     // ff block time by 20 days and stake again
     chain.tick_block_time(Duration::from_days(DAYS)).unwrap();
@@ -256,7 +268,7 @@ fn test_stake_cis2_tokens() {
     // calculate the reward time and amount
     // this algorithm should be determistic and rewards can always be calculated
     // after a given amount of time in `DAYS`
-    let reward = calculate_reward(&mut chain, gona_stake_address, alice_public_key);
+    let reward = calculate_reward(&mut chain, gona_stake_address, POSITION);
     let reward_for_1st_stake = calculate_percent(STAKE_AMOUNT, WEIGHT, DECIMALS) * reward.days;
     println!(
         "amount:{}, days: {}, reward:{}",
@@ -288,6 +300,7 @@ fn test_unstake_cis2_tokens_of_chaperone() {
         cis2_token_contract_address,
         alice_public_key,
         gona_stake_address,
+        POOL_REWARD_AMOUNT,
     );
 
     let service_fee_amount: TokenAmountU256 = TokenAmountU256(0.into());
@@ -369,8 +382,8 @@ fn test_unstake_cis2_tokens_of_chaperone() {
         .expect("Should be able to withdraw cis2 tokens");
 
     // assert if the stake was successful
-    let stake = get_stake_query(&mut chain, gona_stake_address, alice_public_key);
-    assert_eq!(stake.is_some(), true, "Stake did not return");
+    let stake = get_stake_query(&mut chain, gona_stake_address, POSITION);
+    assert!(stake.is_some(), "Stake did not return");
 
     // assert stake amount should match amount that was staked
     assert_eq!(
@@ -386,7 +399,7 @@ fn test_unstake_cis2_tokens_of_chaperone() {
     // calculate the reward time and amount
     // this algorithm should be determistic and rewards can always be calculated
     // after a given amount of time in `DAYS`
-    let reward = calculate_reward(&mut chain, gona_stake_address, alice_public_key);
+    let reward = calculate_reward(&mut chain, gona_stake_address, POSITION);
     let reward_for_1st_stake = calculate_percent(STAKE_AMOUNT, WEIGHT, DECIMALS) * reward.days;
     println!(
         "amount:{}, days: {}, reward:{}",
@@ -398,22 +411,22 @@ fn test_unstake_cis2_tokens_of_chaperone() {
         "reward amount should be deterministic"
     );
 
-    release_stake(
+    let _update = release_stake(
         &mut chain,
         gona_stake_address,
-        alice_public_key,
-        signing_key,
+        POSITION,
+        &signing_key,
         WITHDRAW_STAKE_AMOUNT - 10_000_000_000,
     );
 
-    let reward_after_release = calculate_reward(&mut chain, gona_stake_address, alice_public_key);
+    let reward_after_release = calculate_reward(&mut chain, gona_stake_address, POSITION);
     println!(
         "reward after release: {}",
         reward_after_release.amount_staked
     );
 
-    let stake = get_stake_query(&mut chain, gona_stake_address, alice_public_key)
-        .expect("stake should exist");
+    let stake =
+        get_stake_query(&mut chain, gona_stake_address, POSITION).expect("stake should exist");
     println!("the stake: {:?}", stake);
 
     let res = view_reward_amount(&mut chain, gona_stake_address);
@@ -423,206 +436,3207 @@ fn test_unstake_cis2_tokens_of_chaperone() {
     )
 }
 
-/// Setup chain and contract.
-///
-/// Also creates the three accounts, Alice, Bob, and Charlie.
-///
-/// Alice is the owner of the contract.
-fn initialize_chain_and_contract() -> (Chain, ContractAddress, ContractAddress, ContractAddress) {
-    let mut chain = Chain::new();
+#[test]
+fn test_unstake_pays_partial_rewards_when_pool_is_underfunded() {
+    let (mut chain, smart_contract_wallet, cis2_token_contract_address, gona_stake_address) =
+        initialize_chain_and_contract();
 
-    // Create some accounts on the chain.
-    chain.create_account(Account::new(ALICE, ACC_INITIAL_BALANCE));
-    chain.create_account(Account::new(BOB, ACC_INITIAL_BALANCE));
-    chain.create_account(Account::new(CHARLIE, ACC_INITIAL_BALANCE));
+    let signing_key = ed25519_dalek::SigningKey::generate(&mut rand::thread_rng());
+    let alice_public_key = PublicKeyEd25519(signing_key.verifying_key().to_bytes());
 
-    let metadata = SetMetadataUrlParams {
-        url: "https://www.example.come".to_string(),
-        hash: None,
+    // only fund the pool with a fraction of the rewards that will be owed
+    let pool_amount = 10_000_000;
+    alice_deposits_cis2_tokens_and_fund_pool(
+        &mut chain,
+        smart_contract_wallet,
+        cis2_token_contract_address,
+        alice_public_key,
+        gona_stake_address,
+        pool_amount,
+    );
+    stake_from_smart_wallet(
+        &mut chain,
+        smart_contract_wallet,
+        cis2_token_contract_address,
+        gona_stake_address,
+        &signing_key,
+        STAKE_AMOUNT,
+        0,
+    )
+    .expect("Should be able to stake from the smart wallet");
+
+    chain
+        .tick_block_time(Duration::from_days(DAYS))
+        .expect("days should be ticked");
+
+    let rewards = calculate_percent(STAKE_AMOUNT, WEIGHT, DECIMALS) * DAYS;
+    let update = release_stake(
+        &mut chain,
+        gona_stake_address,
+        POSITION,
+        &signing_key,
+        STAKE_AMOUNT,
+    );
+
+    // the principal is paid out together with whatever the pool could afford
+    let events = staking_events(&update, gona_stake_address);
+    assert!(events.contains(&StakingEvent::PartialRewardPayout {
+        position: POSITION,
+        staker: Staker::PublicKey(alice_public_key),
+        paid: TokenAmountU64(pool_amount),
+        owed: TokenAmountU64(rewards - pool_amount),
+    }));
+    assert_eq!(view_reward_amount(&mut chain, gona_stake_address), 0);
+
+    let stake = get_stake_query(&mut chain, gona_stake_address, POSITION)
+        .expect("stake should be kept while rewards are owed");
+    assert_eq!(stake.amount, TokenAmountU64(0));
+    assert_eq!(stake.pending_rewards, rewards - pool_amount);
+
+    // claiming is rejected until the pool is refilled
+    claim_rewards(&mut chain, gona_stake_address, &signing_key, POSITION)
+        .expect_err("an empty pool should not pay out rewards");
+
+    fund_reward_pool(
+        &mut chain,
+        cis2_token_contract_address,
+        gona_stake_address,
+        POOL_REWARD_AMOUNT,
+    );
+    let update = claim_rewards(&mut chain, gona_stake_address, &signing_key, POSITION)
+        .expect("Should be able to claim pending rewards");
+    let events = staking_events(&update, gona_stake_address);
+    assert!(events.contains(&StakingEvent::RewardsClaimed {
+        position: POSITION,
+        staker: Staker::PublicKey(alice_public_key),
+        amount: TokenAmountU64(rewards - pool_amount),
+        fee: TokenAmountU64(0),
+        remaining: TokenAmountU64(0),
+    }));
+
+    assert_eq!(
+        view_reward_amount(&mut chain, gona_stake_address),
+        POOL_REWARD_AMOUNT - (rewards - pool_amount)
+    );
+    assert!(
+        get_stake_query(&mut chain, gona_stake_address, POSITION).is_none(),
+        "stake should be removed once all rewards are claimed"
+    );
+}
+
+#[test]
+fn test_emergency_withdraw_returns_principal_and_forfeits_rewards() {
+    let (mut chain, smart_contract_wallet, cis2_token_contract_address, gona_stake_address) =
+        initialize_chain_and_contract();
+
+    let signing_key = ed25519_dalek::SigningKey::generate(&mut rand::thread_rng());
+    let alice_public_key = PublicKeyEd25519(signing_key.verifying_key().to_bytes());
+
+    alice_deposits_cis2_tokens_and_fund_pool(
+        &mut chain,
+        smart_contract_wallet,
+        cis2_token_contract_address,
+        alice_public_key,
+        gona_stake_address,
+        POOL_REWARD_AMOUNT,
+    );
+    stake_from_smart_wallet(
+        &mut chain,
+        smart_contract_wallet,
+        cis2_token_contract_address,
+        gona_stake_address,
+        &signing_key,
+        STAKE_AMOUNT,
+        0,
+    )
+    .expect("Should be able to stake from the smart wallet");
+    chain
+        .tick_block_time(Duration::from_days(DAYS))
+        .expect("days should be ticked");
+
+    emergency_withdraw(&mut chain, gona_stake_address, POSITION)
+        .expect_err("emergency withdraw should require emergency mode");
+
+    admin_update(&mut chain, gona_stake_address, "set_paused", &());
+    update_settings(
+        &mut chain,
+        gona_stake_address,
+        SettingUpdate::Emergency(true),
+    );
+
+    emergency_withdraw(&mut chain, gona_stake_address, POSITION)
+        .expect_err("only the key can withdraw its position");
+
+    let message = PermitMessage {
+        contract_address: gona_stake_address,
+        nonce: 0,
+        timestamp: chain
+            .block_time()
+            .checked_add(Duration::from_days(1))
+            .unwrap(),
+        entry_point: OwnedEntrypointName::new_unchecked("emergency_withdraw".to_string()),
+        payload: to_bytes(&POSITION),
     };
-    // Load and deploy cis2 token module.
-    let module = module_load_v1("tests/cis2_token/module.wasm.v1").expect("Module exists");
-    let deployment = chain
-        .module_deploy_v1_debug(SIGNER, ALICE, module, true)
-        .expect("Deploy valid module");
+    let param = sign_permit(&mut chain, gona_stake_address, &signing_key, message);
+    let update = permit(&mut chain, gona_stake_address, &param)
+        .expect("Should be able to withdraw while paused in emergency mode");
+    let events = staking_events(&update, gona_stake_address);
+    assert_eq!(
+        events,
+        vec![
+            StakingEvent::EmergencyWithdraw {
+                position: POSITION,
+                staker: Staker::PublicKey(alice_public_key),
+                amount: TokenAmountU64(STAKE_AMOUNT),
+                forfeited_rewards: TokenAmountU64(
+                    calculate_percent(STAKE_AMOUNT, WEIGHT, DECIMALS) * DAYS
+                ),
+                time: chain.block_time(),
+            },
+            StakingEvent::Burn(BurnEvent {
+                token_id: position_token_id(POSITION),
+                amount: TokenAmountU64(1),
+                owner: Address::Contract(smart_contract_wallet),
+            }),
+            StakingEvent::Nonce {
+                signer: alice_public_key,
+                nonce: 0,
+            },
+        ]
+    );
 
-    // Initialize the token contract.
-    let cis2_token_contract_init = chain
-        .contract_init(
-            SIGNER,
+    assert!(get_stake_query(&mut chain, gona_stake_address, POSITION).is_none());
+    assert_eq!(
+        view_reward_amount(&mut chain, gona_stake_address),
+        POOL_REWARD_AMOUNT,
+        "no rewards should be paid out"
+    );
+}
+
+#[test]
+fn test_account_stakes_and_unstakes_without_smart_wallet() {
+    let (mut chain, smart_contract_wallet, cis2_token_contract_address, gona_stake_address) =
+        initialize_chain_and_contract();
+
+    alice_deposits_cis2_tokens_and_fund_pool(
+        &mut chain,
+        smart_contract_wallet,
+        cis2_token_contract_address,
+        SERVICE_FEE_RECIPIENT_KEY,
+        gona_stake_address,
+        POOL_REWARD_AMOUNT,
+    );
+    let balance_before = token_balance_of(&mut chain, cis2_token_contract_address, ALICE_ADDR);
+
+    stake_from_account(
+        &mut chain,
+        cis2_token_contract_address,
+        gona_stake_address,
+        STAKE_AMOUNT,
+    );
+    let stake = get_stake_query(&mut chain, gona_stake_address, POSITION)
+        .expect("Alice should have a stake");
+    assert_eq!(stake.amount, TokenAmountU64(STAKE_AMOUNT));
+    assert_eq!(stake.owner, Staker::Address(ALICE_ADDR));
+
+    chain
+        .tick_block_time(Duration::from_days(DAYS))
+        .expect("days should be ticked");
+
+    let param = UnstakeParam {
+        amount: TokenAmountU64(STAKE_AMOUNT),
+        position: POSITION,
+        payout: None,
+        signature: None,
+    };
+    unstake(&mut chain, BOB, gona_stake_address, &param)
+        .expect_err("only Alice should be able to unstake her stake");
+    unstake(&mut chain, ALICE, gona_stake_address, &param)
+        .expect("Alice should be able to unstake");
+
+    let rewards = calculate_percent(STAKE_AMOUNT, WEIGHT, DECIMALS) * DAYS;
+    assert_eq!(
+        token_balance_of(&mut chain, cis2_token_contract_address, ALICE_ADDR),
+        balance_before + rewards,
+        "principal and rewards should be paid to Alice's account"
+    );
+    assert!(get_stake_query(&mut chain, gona_stake_address, POSITION).is_none());
+}
+
+#[test]
+fn test_unstake_to_signed_payout_receiver() {
+    let (mut chain, smart_contract_wallet, cis2_token_contract_address, gona_stake_address) =
+        initialize_chain_and_contract();
+
+    use ed25519_dalek::Signer;
+
+    let signing_key = ed25519_dalek::SigningKey::generate(&mut rand::thread_rng());
+    let alice_public_key = PublicKeyEd25519(signing_key.verifying_key().to_bytes());
+
+    alice_deposits_cis2_tokens_and_fund_pool(
+        &mut chain,
+        smart_contract_wallet,
+        cis2_token_contract_address,
+        alice_public_key,
+        gona_stake_address,
+        POOL_REWARD_AMOUNT,
+    );
+    stake_from_smart_wallet(
+        &mut chain,
+        smart_contract_wallet,
+        cis2_token_contract_address,
+        gona_stake_address,
+        &signing_key,
+        STAKE_AMOUNT,
+        0,
+    )
+    .expect("Should be able to stake from the smart wallet");
+
+    let payout = Payout {
+        to: Receiver::Account(BOB),
+        data: AdditionalData::empty(),
+    };
+    let message = PayoutMessage {
+        contract_address: gona_stake_address,
+        staker: alice_public_key,
+        position: POSITION,
+        amount: TokenAmountU64(WITHDRAW_STAKE_AMOUNT),
+        payout: payout.clone(),
+        nonce: 0,
+        expiry_time: chain
+            .block_time()
+            .checked_add(Duration::from_days(1))
+            .unwrap(),
+    };
+    let hash: [u8; 32] = chain
+        .contract_invoke(
             ALICE,
+            ALICE_ADDR,
             Energy::from(10000),
-            InitContractPayload {
+            UpdateContractPayload {
                 amount: Amount::zero(),
-                mod_ref: deployment.module_reference,
-                init_name: OwnedContractName::new_unchecked("init_gona_token".to_string()),
-                param: OwnedParameter::from_serial(&metadata).expect("Token amount params"),
+                receive_name: OwnedReceiveName::new_unchecked(
+                    "gona_stake.payout_message_hash".to_string(),
+                ),
+                address: gona_stake_address,
+                message: OwnedParameter::from_serial(&message).expect("Payout message"),
             },
         )
-        .expect("Initialize contract");
+        .expect("Should be able to query payout_message_hash")
+        .parse_return_value()
+        .expect("Should return a hash");
 
-    // Load and deploy the smart wallet module.
-    let module = module_load_v1("tests/chaperone/module.wasm.v1").expect("Module exists");
-    let deployment = chain
-        .module_deploy_v1_debug(SIGNER, ALICE, module, true)
-        .expect("Deploy valid module");
+    let mut param = UnstakeParam {
+        amount: TokenAmountU64(WITHDRAW_STAKE_AMOUNT),
+        position: POSITION,
+        payout: Some(payout),
+        signature: None,
+    };
+    unstake(&mut chain, CHARLIE, gona_stake_address, &param)
+        .expect_err("a custom payout needs the staker's signature");
+
+    param.signature = Some(PayoutSignature {
+        signature: SignatureEd25519(signing_key.sign(&hash).to_bytes()),
+        nonce: 0,
+        expiry_time: message.expiry_time,
+    });
+
+    update_settings(
+        &mut chain,
+        gona_stake_address,
+        SettingUpdate::PayoutAllowlistEnabled(true),
+    );
+    unstake(&mut chain, CHARLIE, gona_stake_address, &param)
+        .expect_err("Bob is not on the payout allowlist");
+
+    admin_update(
+        &mut chain,
+        gona_stake_address,
+        "update_payout_allowlist",
+        &UpdatePayoutAllowlistParams {
+            add: vec![Address::Account(BOB)],
+            remove: vec![],
+        },
+    );
+    let bob_balance = token_balance_of(
+        &mut chain,
+        cis2_token_contract_address,
+        Address::Account(BOB),
+    );
+    let update = unstake(&mut chain, CHARLIE, gona_stake_address, &param)
+        .expect("Should be able to unstake to Bob");
+    assert!(
+        staking_events(&update, gona_stake_address).contains(&StakingEvent::Nonce {
+            signer: alice_public_key,
+            nonce: 0,
+        })
+    );
+    assert_eq!(
+        token_balance_of(
+            &mut chain,
+            cis2_token_contract_address,
+            Address::Account(BOB)
+        ),
+        bob_balance + WITHDRAW_STAKE_AMOUNT
+    );
+
+    unstake(&mut chain, CHARLIE, gona_stake_address, &param)
+        .expect_err("a signature cannot be replayed");
+}
+
+#[test]
+fn test_stake_and_unstake_through_second_smart_wallet() {
+    let (mut chain, _, cis2_token_contract_address, gona_stake_address) =
+        initialize_chain_and_contract();
+
+    let other_wallet = init_smart_contract_wallet(&mut chain);
+    let signing_key = ed25519_dalek::SigningKey::generate(&mut rand::thread_rng());
+    let alice_public_key = PublicKeyEd25519(signing_key.verifying_key().to_bytes());
+
+    alice_deposits_cis2_tokens_and_fund_pool(
+        &mut chain,
+        other_wallet,
+        cis2_token_contract_address,
+        alice_public_key,
+        gona_stake_address,
+        POOL_REWARD_AMOUNT,
+    );
+    stake_from_smart_wallet(
+        &mut chain,
+        other_wallet,
+        cis2_token_contract_address,
+        gona_stake_address,
+        &signing_key,
+        STAKE_AMOUNT,
+        0,
+    )
+    .expect_err("the wallet is not trusted yet");
+
+    admin_update(
+        &mut chain,
+        gona_stake_address,
+        "update_smart_wallets",
+        &UpdateSmartWalletsParams {
+            add: vec![other_wallet],
+            remove: vec![],
+        },
+    );
+    stake_from_smart_wallet(
+        &mut chain,
+        other_wallet,
+        cis2_token_contract_address,
+        gona_stake_address,
+        &signing_key,
+        STAKE_AMOUNT,
+        0,
+    )
+    .expect("Should be able to stake from the trusted wallet");
+
+    let stake = get_stake_query(&mut chain, gona_stake_address, POSITION).expect("stake exists");
+    assert_eq!(stake.smart_wallet, Some(other_wallet));
+    assert_eq!(
+        wallet_balance_of(
+            &mut chain,
+            other_wallet,
+            cis2_token_contract_address,
+            alice_public_key
+        ),
+        ALICE_KEY_AMOUNT - STAKE_AMOUNT
+    );
+
+    let _update = release_stake(
+        &mut chain,
+        gona_stake_address,
+        POSITION,
+        &signing_key,
+        STAKE_AMOUNT,
+    );
+    assert_eq!(
+        wallet_balance_of(
+            &mut chain,
+            other_wallet,
+            cis2_token_contract_address,
+            alice_public_key
+        ),
+        ALICE_KEY_AMOUNT,
+        "the stake should be paid back to the wallet it came from"
+    );
+}
+
+#[test]
+fn test_sponsored_unstake_through_permit() {
+    let (mut chain, smart_contract_wallet, cis2_token_contract_address, gona_stake_address) =
+        initialize_chain_and_contract();
+
+    let signing_key = ed25519_dalek::SigningKey::generate(&mut rand::thread_rng());
+    let alice_public_key = PublicKeyEd25519(signing_key.verifying_key().to_bytes());
+    let other_key = ed25519_dalek::SigningKey::generate(&mut rand::thread_rng());
+
+    alice_deposits_cis2_tokens_and_fund_pool(
+        &mut chain,
+        smart_contract_wallet,
+        cis2_token_contract_address,
+        alice_public_key,
+        gona_stake_address,
+        POOL_REWARD_AMOUNT,
+    );
+    stake_from_smart_wallet(
+        &mut chain,
+        smart_contract_wallet,
+        cis2_token_contract_address,
+        gona_stake_address,
+        &signing_key,
+        STAKE_AMOUNT,
+        0,
+    )
+    .expect("Should be able to stake from the smart wallet");
+
+    let supports: SupportsQueryResponse = chain
+        .contract_invoke(
+            ALICE,
+            ALICE_ADDR,
+            Energy::from(10000),
+            UpdateContractPayload {
+                amount: Amount::zero(),
+                receive_name: OwnedReceiveName::new_unchecked(
+                    "gona_stake.supportsPermit".to_string(),
+                ),
+                address: gona_stake_address,
+                message: OwnedParameter::from_serial(&SupportsPermitQueryParams {
+                    queries: vec![
+                        OwnedEntrypointName::new_unchecked("unstake".to_string()),
+                        OwnedEntrypointName::new_unchecked("stake".to_string()),
+                    ],
+                })
+                .expect("Supports permit params"),
+            },
+        )
+        .expect("Should be able to query supportsPermit")
+        .parse_return_value()
+        .expect("Supports response");
+    assert!(matches!(
+        supports.results[..],
+        [SupportResult::Support, SupportResult::NoSupport]
+    ));
+    assert_eq!(
+        nonce_of(&mut chain, gona_stake_address, alice_public_key),
+        0
+    );
+
+    let unstake_param = UnstakeParam {
+        amount: TokenAmountU64(STAKE_AMOUNT),
+        position: POSITION,
+        payout: None,
+        signature: None,
+    };
+    let message = PermitMessage {
+        contract_address: gona_stake_address,
+        nonce: 0,
+        timestamp: chain
+            .block_time()
+            .checked_add(Duration::from_days(1))
+            .unwrap(),
+        entry_point: OwnedEntrypointName::new_unchecked("unstake".to_string()),
+        payload: to_bytes(&unstake_param),
+    };
+
+    // nobody else can unstake it without the key's consent
+    unstake(&mut chain, ALICE, gona_stake_address, &unstake_param)
+        .expect_err("a stake of a key needs its signature");
+
+    // a key cannot unstake a stake that belongs to another key
+    let forged = sign_permit(&mut chain, gona_stake_address, &other_key, message.clone());
+    permit(&mut chain, gona_stake_address, &forged)
+        .expect_err("only the staker can sign a permit for its stake");
+
+    let param = sign_permit(&mut chain, gona_stake_address, &signing_key, message);
+    let update = permit(&mut chain, gona_stake_address, &param)
+        .expect("Charlie should be able to sponsor the unstake");
+    assert!(
+        staking_events(&update, gona_stake_address).contains(&StakingEvent::Nonce {
+            signer: alice_public_key,
+            nonce: 0,
+        })
+    );
+    assert_eq!(
+        wallet_balance_of(
+            &mut chain,
+            smart_contract_wallet,
+            cis2_token_contract_address,
+            alice_public_key
+        ),
+        ALICE_KEY_AMOUNT
+    );
+    assert_eq!(
+        nonce_of(&mut chain, gona_stake_address, alice_public_key),
+        1
+    );
+
+    permit(&mut chain, gona_stake_address, &param).expect_err("a permit cannot be replayed");
+}
+
+#[test]
+fn test_supports_standards() {
+    let (chain, _, _, gona_stake_address) = initialize_chain_and_contract();
+
+    let params = SupportsQueryParams {
+        queries: vec![
+            CIS0_STANDARD_IDENTIFIER.to_owned(),
+            // permits are signed by public keys, not CIS-3 account signatures
+            StandardIdentifierOwned::new_unchecked("CIS-3".to_string()),
+            STAKING_STANDARD_IDENTIFIER.to_owned(),
+            CIS2_STANDARD_IDENTIFIER.to_owned(),
+        ],
+    };
+    let supports: SupportsQueryResponse = chain
+        .contract_invoke(
+            ALICE,
+            ALICE_ADDR,
+            Energy::from(10000),
+            UpdateContractPayload {
+                amount: Amount::zero(),
+                receive_name: OwnedReceiveName::new_unchecked("gona_stake.supports".to_string()),
+                address: gona_stake_address,
+                message: OwnedParameter::from_serial(&params).expect("Supports params"),
+            },
+        )
+        .expect("Should be able to query supports")
+        .parse_return_value()
+        .expect("Supports response");
+    assert!(matches!(
+        supports.results[..],
+        [
+            SupportResult::Support,
+            SupportResult::NoSupport,
+            SupportResult::Support,
+            SupportResult::Support
+        ]
+    ));
+}
+
+#[test]
+fn test_liquid_stake_receipts_grow_in_value() {
+    let (mut chain, smart_contract_wallet, cis2_token_contract_address, gona_stake_address) =
+        initialize_chain_and_contract();
+    let signing_key = ed25519_dalek::SigningKey::generate(&mut rand::thread_rng());
+    let alice_public_key = PublicKeyEd25519(signing_key.verifying_key().to_bytes());
+
+    alice_deposits_cis2_tokens_and_fund_pool(
+        &mut chain,
+        smart_contract_wallet,
+        cis2_token_contract_address,
+        alice_public_key,
+        gona_stake_address,
+        POOL_REWARD_AMOUNT,
+    );
+
+    // Alice stakes from her account and from her key in the smart wallet.
+    transfer_to_hook(
+        &mut chain,
+        cis2_token_contract_address,
+        gona_stake_address,
+        "stake_liquid",
+        STAKE_AMOUNT,
+    )
+    .expect("Should be able to transfer from an account");
+    withdraw_from_smart_wallet(
+        &mut chain,
+        smart_contract_wallet,
+        cis2_token_contract_address,
+        Receiver::Contract(
+            gona_stake_address,
+            OwnedEntrypointName::new_unchecked("stake_liquid".to_owned()),
+        ),
+        &signing_key,
+        STAKE_AMOUNT,
+        0,
+    )
+    .expect("Should be able to stake liquid from the smart wallet");
+    assert_eq!(
+        receipt_balance_of(&mut chain, gona_stake_address, ALICE_ADDR),
+        STAKE_AMOUNT
+    );
+    assert_eq!(
+        wallet_balance_of(
+            &mut chain,
+            smart_contract_wallet,
+            gona_stake_address,
+            alice_public_key
+        ),
+        STAKE_AMOUNT,
+        "the receipts of the key should be held in the smart wallet"
+    );
+
+    // Alice hands half of her receipts to Bob.
+    transfer_receipts(
+        &mut chain,
+        BOB,
+        gona_stake_address,
+        Receiver::Account(BOB),
+        WITHDRAW_STAKE_AMOUNT,
+    )
+    .expect_err("Bob cannot move Alice's receipts");
+    let update = transfer_receipts(
+        &mut chain,
+        ALICE,
+        gona_stake_address,
+        Receiver::Account(BOB),
+        WITHDRAW_STAKE_AMOUNT,
+    )
+    .expect("Alice should be able to transfer her receipts");
+    assert_eq!(
+        staking_events(&update, gona_stake_address),
+        [StakingEvent::Transfer(TransferEvent {
+            token_id: RECEIPT_TOKEN_ID,
+            amount: TokenAmountU64(WITHDRAW_STAKE_AMOUNT),
+            from: ALICE_ADDR,
+            to: BOB_ADDR,
+        })]
+    );
+
+    chain
+        .tick_block_time(Duration::from_days(DAYS))
+        .expect("days should be ticked");
+    let pool = view_liquid_pool(&mut chain, gona_stake_address);
+    let rewards = calculate_percent(2 * STAKE_AMOUNT, WEIGHT, DECIMALS) * DAYS;
+    assert_eq!(pool.total_assets, 2 * STAKE_AMOUNT + rewards);
+    assert_eq!(pool.total_shares, 2 * STAKE_AMOUNT);
+
+    // Bob redeems his receipts for principal plus rewards.
+    let update = transfer_receipts(
+        &mut chain,
+        BOB,
+        gona_stake_address,
+        Receiver::Contract(
+            gona_stake_address,
+            OwnedEntrypointName::new_unchecked("unstake_liquid".to_owned()),
+        ),
+        WITHDRAW_STAKE_AMOUNT,
+    )
+    .expect("Bob should be able to redeem his receipts");
+    let bob_amount = share_value(WITHDRAW_STAKE_AMOUNT, pool.total_assets, pool.total_shares);
+    assert!(bob_amount > WITHDRAW_STAKE_AMOUNT);
+    assert_eq!(
+        token_balance_of(&mut chain, cis2_token_contract_address, BOB_ADDR),
+        bob_amount
+    );
+    assert_eq!(
+        receipt_balance_of(&mut chain, gona_stake_address, BOB_ADDR),
+        0
+    );
+    assert!(
+        staking_events(&update, gona_stake_address).contains(&StakingEvent::LiquidUnstaked {
+            staker: Staker::Address(BOB_ADDR),
+            shares: TokenAmountU64(WITHDRAW_STAKE_AMOUNT),
+            amount: TokenAmountU64(bob_amount),
+        })
+    );
+
+    // Alice's key redeems its receipts back into the smart wallet.
+    withdraw_from_smart_wallet(
+        &mut chain,
+        smart_contract_wallet,
+        gona_stake_address,
+        Receiver::Contract(
+            gona_stake_address,
+            OwnedEntrypointName::new_unchecked("unstake_liquid".to_owned()),
+        ),
+        &signing_key,
+        STAKE_AMOUNT,
+        1,
+    )
+    .expect("Should be able to redeem receipts from the smart wallet");
+    let key_amount = share_value(
+        STAKE_AMOUNT,
+        pool.total_assets - bob_amount,
+        pool.total_shares - WITHDRAW_STAKE_AMOUNT,
+    );
+    assert_eq!(
+        wallet_balance_of(
+            &mut chain,
+            smart_contract_wallet,
+            cis2_token_contract_address,
+            alice_public_key
+        ),
+        ALICE_KEY_AMOUNT - STAKE_AMOUNT + key_amount
+    );
+    assert_eq!(
+        wallet_balance_of(
+            &mut chain,
+            smart_contract_wallet,
+            gona_stake_address,
+            alice_public_key
+        ),
+        0
+    );
+}
+
+#[test]
+fn test_transferring_a_position_moves_its_rewards() {
+    let (mut chain, smart_contract_wallet, cis2_token_contract_address, gona_stake_address) =
+        initialize_chain_and_contract();
+    let signing_key = ed25519_dalek::SigningKey::generate(&mut rand::thread_rng());
+    let alice_public_key = PublicKeyEd25519(signing_key.verifying_key().to_bytes());
+
+    alice_deposits_cis2_tokens_and_fund_pool(
+        &mut chain,
+        smart_contract_wallet,
+        cis2_token_contract_address,
+        alice_public_key,
+        gona_stake_address,
+        POOL_REWARD_AMOUNT,
+    );
+    stake_from_smart_wallet(
+        &mut chain,
+        smart_contract_wallet,
+        cis2_token_contract_address,
+        gona_stake_address,
+        &signing_key,
+        STAKE_AMOUNT,
+        0,
+    )
+    .expect("Should be able to stake from the smart wallet");
+    stake_from_account(
+        &mut chain,
+        cis2_token_contract_address,
+        gona_stake_address,
+        STAKE_AMOUNT,
+    );
+
+    // every stake is its own position NFT
+    assert_eq!(
+        wallet_token_balance_of(
+            &mut chain,
+            smart_contract_wallet,
+            gona_stake_address,
+            position_token_id(POSITION),
+            alice_public_key
+        ),
+        1,
+        "the position of the key should be held in the smart wallet"
+    );
+    let account_position = POSITION + 1;
+    let token_id = position_token_id(account_position);
+    assert_eq!(
+        stake_token_balance_of(&mut chain, gona_stake_address, token_id.clone(), ALICE_ADDR),
+        1
+    );
+
+    transfer_stake_tokens(
+        &mut chain,
+        BOB,
+        gona_stake_address,
+        token_id.clone(),
+        Receiver::Account(BOB),
+        1,
+    )
+    .expect_err("Bob cannot take Alice's position");
+    let update = transfer_stake_tokens(
+        &mut chain,
+        ALICE,
+        gona_stake_address,
+        token_id.clone(),
+        Receiver::Account(BOB),
+        1,
+    )
+    .expect("Alice should be able to transfer her position");
+    assert!(staking_events(&update, gona_stake_address).contains(
+        &StakingEvent::PositionTransferred {
+            position: account_position,
+            owner: Staker::Address(BOB_ADDR),
+        }
+    ));
+    assert_eq!(
+        stake_token_balance_of(&mut chain, gona_stake_address, token_id.clone(), ALICE_ADDR),
+        0
+    );
+    assert_eq!(
+        stake_token_balance_of(&mut chain, gona_stake_address, token_id.clone(), BOB_ADDR),
+        1
+    );
+    let stake = get_stake_query(&mut chain, gona_stake_address, account_position)
+        .expect("the position should still exist");
+    assert_eq!(stake.owner, Staker::Address(BOB_ADDR));
+
+    chain
+        .tick_block_time(Duration::from_days(DAYS))
+        .expect("days should be ticked");
+
+    let param = UnstakeParam {
+        amount: TokenAmountU64(STAKE_AMOUNT),
+        position: account_position,
+        payout: None,
+        signature: None,
+    };
+    unstake(&mut chain, ALICE, gona_stake_address, &param)
+        .expect_err("Alice no longer owns the position");
+    let update = unstake(&mut chain, BOB, gona_stake_address, &param)
+        .expect("Bob should be able to unstake the position");
+
+    let rewards = calculate_percent(STAKE_AMOUNT, WEIGHT, DECIMALS) * DAYS;
+    assert_eq!(
+        token_balance_of(&mut chain, cis2_token_contract_address, BOB_ADDR),
+        STAKE_AMOUNT + rewards,
+        "the rewards accrued while Alice held the position go to Bob"
+    );
+    assert!(
+        staking_events(&update, gona_stake_address).contains(&StakingEvent::Burn(BurnEvent {
+            token_id,
+            amount: TokenAmountU64(1),
+            owner: BOB_ADDR,
+        }))
+    );
+    assert!(get_stake_query(&mut chain, gona_stake_address, account_position).is_none());
+}
+
+#[test]
+fn test_staker_operators_manage_positions() {
+    let (mut chain, smart_contract_wallet, cis2_token_contract_address, gona_stake_address) =
+        initialize_chain_and_contract();
+    let signing_key = ed25519_dalek::SigningKey::generate(&mut rand::thread_rng());
+    let alice_public_key = PublicKeyEd25519(signing_key.verifying_key().to_bytes());
+
+    alice_deposits_cis2_tokens_and_fund_pool(
+        &mut chain,
+        smart_contract_wallet,
+        cis2_token_contract_address,
+        alice_public_key,
+        gona_stake_address,
+        POOL_REWARD_AMOUNT,
+    );
+    stake_from_smart_wallet(
+        &mut chain,
+        smart_contract_wallet,
+        cis2_token_contract_address,
+        gona_stake_address,
+        &signing_key,
+        STAKE_AMOUNT,
+        0,
+    )
+    .expect("Should be able to stake from the smart wallet");
+
+    let param = UnstakeParam {
+        amount: TokenAmountU64(WITHDRAW_STAKE_AMOUNT),
+        position: POSITION,
+        payout: Some(Payout {
+            to: Receiver::Account(BOB),
+            data: AdditionalData::empty(),
+        }),
+        signature: None,
+    };
+    unstake(&mut chain, BOB, gona_stake_address, &param)
+        .expect_err("Bob is not an operator of the key yet");
+
+    // the key approves Bob through a sponsored permit
+    let updates = UpdateOperatorParams(vec![UpdateOperator {
+        update: OperatorUpdate::Add,
+        operator: BOB_ADDR,
+    }]);
+    let message = PermitMessage {
+        contract_address: gona_stake_address,
+        nonce: 0,
+        timestamp: chain
+            .block_time()
+            .checked_add(Duration::from_days(1))
+            .unwrap(),
+        entry_point: OwnedEntrypointName::new_unchecked("update_staker_operators".to_string()),
+        payload: to_bytes(&updates),
+    };
+    let permit_param = sign_permit(&mut chain, gona_stake_address, &signing_key, message);
+    let update = permit(&mut chain, gona_stake_address, &permit_param)
+        .expect("Charlie should be able to sponsor the operator update");
+    assert!(
+        staking_events(&update, gona_stake_address).contains(&StakingEvent::OperatorUpdated {
+            owner: Staker::PublicKey(alice_public_key),
+            operator: BOB_ADDR,
+            update: OperatorUpdate::Add,
+        })
+    );
+    assert!(staker_operator_of(
+        &mut chain,
+        gona_stake_address,
+        Staker::PublicKey(alice_public_key),
+        BOB_ADDR
+    ));
+
+    unstake(&mut chain, CHARLIE, gona_stake_address, &param)
+        .expect_err("Charlie is not an operator of the key");
+    unstake(&mut chain, BOB, gona_stake_address, &param)
+        .expect("an operator can unstake without a payout signature");
+    assert_eq!(
+        token_balance_of(&mut chain, cis2_token_contract_address, BOB_ADDR),
+        WITHDRAW_STAKE_AMOUNT
+    );
+
+    // accounts approve operators directly
+    stake_from_account(
+        &mut chain,
+        cis2_token_contract_address,
+        gona_stake_address,
+        STAKE_AMOUNT,
+    );
+    let param = UnstakeParam {
+        amount: TokenAmountU64(STAKE_AMOUNT),
+        position: POSITION + 1,
+        payout: None,
+        signature: None,
+    };
+    unstake(&mut chain, BOB, gona_stake_address, &param)
+        .expect_err("Bob is not an operator of Alice yet");
+    admin_update(
+        &mut chain,
+        gona_stake_address,
+        "update_staker_operators",
+        &updates,
+    );
+    let alice_balance = token_balance_of(&mut chain, cis2_token_contract_address, ALICE_ADDR);
+    unstake(&mut chain, BOB, gona_stake_address, &param)
+        .expect("Bob should be able to unstake for Alice");
+    assert_eq!(
+        token_balance_of(&mut chain, cis2_token_contract_address, ALICE_ADDR),
+        alice_balance + STAKE_AMOUNT,
+        "the stake is still paid out to Alice"
+    );
+}
+
+#[test]
+fn test_compound_rolls_rewards_into_principal() {
+    let (mut chain, smart_contract_wallet, cis2_token_contract_address, gona_stake_address) =
+        initialize_chain_and_contract();
+
+    alice_deposits_cis2_tokens_and_fund_pool(
+        &mut chain,
+        smart_contract_wallet,
+        cis2_token_contract_address,
+        SERVICE_FEE_RECIPIENT_KEY,
+        gona_stake_address,
+        POOL_REWARD_AMOUNT,
+    );
+    stake_from_account(
+        &mut chain,
+        cis2_token_contract_address,
+        gona_stake_address,
+        STAKE_AMOUNT,
+    );
+    let enable = SetAutoCompoundParam {
+        position: POSITION,
+        enabled: true,
+    };
+    chain
+        .contract_update(
+            SIGNER,
+            BOB,
+            BOB_ADDR,
+            Energy::from(10000),
+            UpdateContractPayload {
+                amount: Amount::zero(),
+                receive_name: OwnedReceiveName::new_unchecked(
+                    "gona_stake.set_auto_compound".to_string(),
+                ),
+                address: gona_stake_address,
+                message: OwnedParameter::from_serial(&enable).expect("Auto compound params"),
+            },
+        )
+        .expect_err("only the owner can enable auto compounding");
+    admin_update(&mut chain, gona_stake_address, "set_auto_compound", &enable);
+
+    chain
+        .tick_block_time(Duration::from_days(DAYS))
+        .expect("days should be ticked");
+    let first_rewards = calculate_percent(STAKE_AMOUNT, WEIGHT, DECIMALS) * DAYS;
+    let update = compound(&mut chain, gona_stake_address, vec![POSITION, 42])
+        .expect("anyone can compound a batch of positions");
+    assert_eq!(
+        staking_events(&update, gona_stake_address),
+        [
+            StakingEvent::Compounded {
+                position: POSITION,
+                staker: Staker::Address(ALICE_ADDR),
+                rewards: TokenAmountU64(first_rewards),
+                amount: TokenAmountU64(STAKE_AMOUNT + first_rewards),
+            },
+            StakingEvent::RewardVolumeChanged {
+                reward_volume: TokenAmountU64(POOL_REWARD_AMOUNT - first_rewards),
+            }
+        ]
+    );
+    let stake = get_stake_query(&mut chain, gona_stake_address, POSITION).expect("stake exists");
+    assert_eq!(stake.amount, TokenAmountU64(STAKE_AMOUNT + first_rewards));
+    assert_eq!(stake.time_of_stake, chain.block_time());
+
+    // compounding again right away adds nothing
+    let update = compound(&mut chain, gona_stake_address, vec![POSITION]).expect("compound");
+    assert!(staking_events(&update, gona_stake_address).is_empty());
+
+    chain
+        .tick_block_time(Duration::from_days(DAYS))
+        .expect("days should be ticked");
+    compound(&mut chain, gona_stake_address, vec![POSITION]).expect("compound");
+    let second_rewards = calculate_percent(STAKE_AMOUNT + first_rewards, WEIGHT, DECIMALS) * DAYS;
+    let stake = get_stake_query(&mut chain, gona_stake_address, POSITION).expect("stake exists");
+    assert_eq!(
+        stake.amount,
+        TokenAmountU64(STAKE_AMOUNT + first_rewards + second_rewards),
+        "the second period earns on the compounded principal"
+    );
+    assert_eq!(
+        view_reward_amount(&mut chain, gona_stake_address),
+        POOL_REWARD_AMOUNT - first_rewards - second_rewards
+    );
+}
+
+#[test]
+fn test_keeper_is_paid_for_compounding() {
+    let (mut chain, smart_contract_wallet, cis2_token_contract_address, gona_stake_address) =
+        initialize_chain_and_contract();
+
+    alice_deposits_cis2_tokens_and_fund_pool(
+        &mut chain,
+        smart_contract_wallet,
+        cis2_token_contract_address,
+        SERVICE_FEE_RECIPIENT_KEY,
+        gona_stake_address,
+        POOL_REWARD_AMOUNT,
+    );
+    for position in [POSITION, POSITION + 1] {
+        stake_from_account(
+            &mut chain,
+            cis2_token_contract_address,
+            gona_stake_address,
+            STAKE_AMOUNT,
+        );
+        admin_update(
+            &mut chain,
+            gona_stake_address,
+            "set_auto_compound",
+            &SetAutoCompoundParam {
+                position,
+                enabled: true,
+            },
+        );
+    }
+    let keeper_bounty = KeeperBounty {
+        per_item: 1_000_000,
+        max_per_call: 1_500_000,
+        max_share_bps: 100,
+    };
+    update_settings(
+        &mut chain,
+        gona_stake_address,
+        SettingUpdate::KeeperBounty(keeper_bounty),
+    );
+
+    chain
+        .tick_block_time(Duration::from_days(DAYS))
+        .expect("days should be ticked");
+    let rewards = calculate_percent(STAKE_AMOUNT, WEIGHT, DECIMALS) * DAYS;
+    let keeper_balance = token_balance_of(&mut chain, cis2_token_contract_address, CHARLIE_ADDR);
+    let update =
+        compound(&mut chain, gona_stake_address, vec![POSITION, POSITION + 1]).expect("compound");
+    let events = staking_events(&update, gona_stake_address);
+    assert!(
+        events.contains(&StakingEvent::KeeperRewarded {
+            keeper: CHARLIE,
+            bounty: TokenAmountU64(keeper_bounty.max_per_call),
+        }),
+        "the bounty is capped per call"
+    );
+    assert_eq!(
+        token_balance_of(&mut chain, cis2_token_contract_address, CHARLIE_ADDR),
+        keeper_balance + keeper_bounty.max_per_call
+    );
+    assert_eq!(
+        view_reward_amount(&mut chain, gona_stake_address),
+        POOL_REWARD_AMOUNT - 2 * rewards - keeper_bounty.max_per_call
+    );
+
+    // nothing compounded, nothing paid
+    let update =
+        compound(&mut chain, gona_stake_address, vec![POSITION, POSITION + 1]).expect("compound");
+    assert!(staking_events(&update, gona_stake_address).is_empty());
+    assert_eq!(
+        token_balance_of(&mut chain, cis2_token_contract_address, CHARLIE_ADDR),
+        keeper_balance + keeper_bounty.max_per_call
+    );
+
+    // compounding dust pays no more than a share of the rewards it moved
+    chain
+        .tick_block_time(Duration::from_hours(1))
+        .expect("an hour should be ticked");
+    let update =
+        compound(&mut chain, gona_stake_address, vec![POSITION, POSITION + 1]).expect("compound");
+    let events = staking_events(&update, gona_stake_address);
+    let compounded: u64 = events
+        .iter()
+        .filter_map(|event| match event {
+            StakingEvent::Compounded { rewards, .. } => Some(rewards.0),
+            _ => None,
+        })
+        .sum();
+    let bounty = compounded * u64::from(keeper_bounty.max_share_bps) / 10_000;
+    assert!(bounty > 0 && bounty < 2 * keeper_bounty.per_item);
+    assert!(events.contains(&StakingEvent::KeeperRewarded {
+        keeper: CHARLIE,
+        bounty: TokenAmountU64(bounty),
+    }));
+
+    let invalid = KeeperBounty {
+        max_share_bps: 10_001,
+        ..keeper_bounty
+    };
+    chain
+        .contract_update(
+            SIGNER,
+            ALICE,
+            ALICE_ADDR,
+            Energy::from(10000),
+            UpdateContractPayload {
+                amount: Amount::zero(),
+                receive_name: OwnedReceiveName::new_unchecked(
+                    "gona_stake.update_settings".to_string(),
+                ),
+                address: gona_stake_address,
+                message: OwnedParameter::from_serial(&SettingUpdate::KeeperBounty(invalid))
+                    .expect("Keeper bounty"),
+            },
+        )
+        .expect_err("the share cannot exceed the rewards");
+}
+
+#[test]
+fn test_epochs_share_budget_by_stake() {
+    let (mut chain, smart_contract_wallet, cis2_token_contract_address, gona_stake_address) =
+        initialize_chain_and_contract();
+
+    alice_deposits_cis2_tokens_and_fund_pool(
+        &mut chain,
+        smart_contract_wallet,
+        cis2_token_contract_address,
+        SERVICE_FEE_RECIPIENT_KEY,
+        gona_stake_address,
+        POOL_REWARD_AMOUNT,
+    );
+    let budget = 1_200_000_000;
+    update_settings(
+        &mut chain,
+        gona_stake_address,
+        SettingUpdate::Epochs(EpochConfig {
+            length: Duration::from_days(1),
+            budget,
+        }),
+    );
+    let start = chain.block_time();
+    assert_eq!(
+        view_epoch(&mut chain, gona_stake_address),
+        EpochInfo {
+            current: 0,
+            next_epoch_start: start.checked_add(Duration::from_days(1)).unwrap(),
+            budget,
+        }
+    );
+
+    let stake = 10_000_000_000;
+    stake_from_account(
+        &mut chain,
+        cis2_token_contract_address,
+        gona_stake_address,
+        stake,
+    );
+    chain
+        .tick_block_time(Duration::from_days(1))
+        .expect("days should be ticked");
+    stake_from_account(
+        &mut chain,
+        cis2_token_contract_address,
+        gona_stake_address,
+        3 * stake,
+    );
+    assert_eq!(
+        calculate_reward(&mut chain, gona_stake_address, POSITION).rewards,
+        0
+    );
+
+    // a partial epoch earns nothing yet
+    chain
+        .tick_block_time(Duration::from_hours(23))
+        .expect("hours should be ticked");
+    assert_eq!(
+        calculate_reward(&mut chain, gona_stake_address, POSITION).rewards,
+        0
+    );
+    chain
+        .tick_block_time(Duration::from_hours(1))
+        .expect("hours should be ticked");
+    assert_eq!(view_epoch(&mut chain, gona_stake_address).current, 2);
+    assert_eq!(
+        calculate_reward(&mut chain, gona_stake_address, POSITION).rewards,
+        budget,
+        "the first position had epoch 1 to itself"
+    );
+    assert_eq!(
+        calculate_reward(&mut chain, gona_stake_address, POSITION + 1).rewards,
+        0
+    );
+
+    chain
+        .tick_block_time(Duration::from_days(1))
+        .expect("days should be ticked");
+    assert_eq!(
+        calculate_reward(&mut chain, gona_stake_address, POSITION).rewards,
+        budget + budget / 4
+    );
+    assert_eq!(
+        calculate_reward(&mut chain, gona_stake_address, POSITION + 1).rewards,
+        3 * budget / 4
+    );
+
+    let balance_before = token_balance_of(&mut chain, cis2_token_contract_address, ALICE_ADDR);
+    let param = UnstakeParam {
+        amount: TokenAmountU64(stake),
+        position: POSITION,
+        payout: None,
+        signature: None,
+    };
+    unstake(&mut chain, ALICE, gona_stake_address, &param)
+        .expect("Alice should be able to unstake");
+    assert_eq!(
+        token_balance_of(&mut chain, cis2_token_contract_address, ALICE_ADDR),
+        balance_before + stake + budget + budget / 4
+    );
+
+    // the remaining position gets the whole budget of the next epoch
+    chain
+        .tick_block_time(Duration::from_days(1))
+        .expect("days should be ticked");
+    assert_eq!(
+        calculate_reward(&mut chain, gona_stake_address, POSITION + 1).rewards,
+        3 * budget / 4 + budget
+    );
+    assert_eq!(
+        view_reward_amount(&mut chain, gona_stake_address),
+        POOL_REWARD_AMOUNT - budget - budget / 4
+    );
+}
+
+#[test]
+fn test_rewards_accrue_by_the_second() {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    let (mut chain, smart_contract_wallet, cis2_token_contract_address, gona_stake_address) =
+        initialize_chain_and_contract();
+
+    alice_deposits_cis2_tokens_and_fund_pool(
+        &mut chain,
+        smart_contract_wallet,
+        cis2_token_contract_address,
+        SERVICE_FEE_RECIPIENT_KEY,
+        gona_stake_address,
+        POOL_REWARD_AMOUNT,
+    );
+    stake_from_account(
+        &mut chain,
+        cis2_token_contract_address,
+        gona_stake_address,
+        STAKE_AMOUNT,
+    );
+    let start = chain.block_time();
+
+    chain
+        .tick_block_time(Duration::from_hours(23))
+        .expect("hours should be ticked");
+    let rewards = calculate_reward(&mut chain, gona_stake_address, POSITION).rewards;
+    assert!(rewards > 0, "a stake of 23 hours earns rewards");
+    assert_eq!(rewards, reference_rewards(STAKE_AMOUNT, 23 * 60 * 60));
+
+    // seeded so that a failing duration can be reproduced
+    let rng = &mut StdRng::seed_from_u64(39);
+    for _ in 0..25 {
+        chain
+            .tick_block_time(Duration::from_millis(rng.gen_range(1..200_000_000)))
+            .expect("time should be ticked");
+        let seconds = chain.block_time().duration_since(start).unwrap().seconds();
+        assert_eq!(
+            calculate_reward(&mut chain, gona_stake_address, POSITION).rewards,
+            reference_rewards(STAKE_AMOUNT, seconds),
+            "rewards after {seconds} seconds"
+        );
+    }
+
+    update_settings(
+        &mut chain,
+        gona_stake_address,
+        SettingUpdate::AccrualGranularity(Duration::from_hours(1)),
+    );
+    for _ in 0..25 {
+        chain
+            .tick_block_time(Duration::from_millis(rng.gen_range(1..20_000_000)))
+            .expect("time should be ticked");
+        let hours = chain.block_time().duration_since(start).unwrap().seconds() / 3600;
+        assert_eq!(
+            calculate_reward(&mut chain, gona_stake_address, POSITION).rewards,
+            reference_rewards(STAKE_AMOUNT, hours * 3600),
+            "rewards after {hours} hours"
+        );
+    }
+}
+
+#[test]
+fn test_stake_limits() {
+    let (mut chain, smart_contract_wallet, cis2_token_contract_address, gona_stake_address) =
+        initialize_chain_and_contract();
+
+    alice_deposits_cis2_tokens_and_fund_pool(
+        &mut chain,
+        smart_contract_wallet,
+        cis2_token_contract_address,
+        SERVICE_FEE_RECIPIENT_KEY,
+        gona_stake_address,
+        POOL_REWARD_AMOUNT,
+    );
+
+    let invalid = StakeLimits {
+        min_stake: 2 * STAKE_AMOUNT,
+        max_per_staker: Some(STAKE_AMOUNT),
+        ..STAKE_LIMITS
+    };
+    chain
+        .contract_update(
+            SIGNER,
+            ALICE,
+            ALICE_ADDR,
+            Energy::from(10000),
+            UpdateContractPayload {
+                amount: Amount::zero(),
+                receive_name: OwnedReceiveName::new_unchecked(
+                    "gona_stake.update_settings".to_string(),
+                ),
+                address: gona_stake_address,
+                message: OwnedParameter::from_serial(&SettingUpdate::StakeLimits(invalid))
+                    .expect("Stake limits"),
+            },
+        )
+        .expect_err("the minimum cannot exceed the maximum");
+
+    let limits = StakeLimits {
+        min_stake: 1_000_000,
+        dust_threshold: 5_000_000,
+        max_per_staker: Some(STAKE_AMOUNT),
+        pool_capacity: None,
+    };
+    update_settings(
+        &mut chain,
+        gona_stake_address,
+        SettingUpdate::StakeLimits(limits),
+    );
+    assert_eq!(view_settings(&mut chain, gona_stake_address).limits, limits);
+
+    transfer_to_hook(
+        &mut chain,
+        cis2_token_contract_address,
+        gona_stake_address,
+        "stake",
+        limits.min_stake - 1,
+    )
+    .expect_err("stakes below the minimum are rejected");
+    stake_from_account(
+        &mut chain,
+        cis2_token_contract_address,
+        gona_stake_address,
+        STAKE_AMOUNT,
+    );
+    transfer_to_hook(
+        &mut chain,
+        cis2_token_contract_address,
+        gona_stake_address,
+        "stake",
+        limits.min_stake,
+    )
+    .expect_err("Alice has staked her maximum");
+    transfer_to_hook(
+        &mut chain,
+        cis2_token_contract_address,
+        gona_stake_address,
+        "stake_liquid",
+        STAKE_AMOUNT,
+    )
+    .expect_err("the liquid pool counts towards her maximum");
+
+    let limits = StakeLimits {
+        max_per_staker: None,
+        pool_capacity: Some(STAKE_AMOUNT + 10_000_000),
+        ..limits
+    };
+    update_settings(
+        &mut chain,
+        gona_stake_address,
+        SettingUpdate::StakeLimits(limits),
+    );
+    transfer_to_hook(
+        &mut chain,
+        cis2_token_contract_address,
+        gona_stake_address,
+        "stake_liquid",
+        20_000_000,
+    )
+    .expect_err("the pool is nearly full");
+    stake_from_account(
+        &mut chain,
+        cis2_token_contract_address,
+        gona_stake_address,
+        10_000_000,
+    );
+    assert_eq!(
+        view_staked(&mut chain, gona_stake_address, Staker::Address(ALICE_ADDR)).total,
+        STAKE_AMOUNT + 10_000_000
+    );
+
+    // leaving less than the dust threshold closes the position
+    let param = UnstakeParam {
+        amount: TokenAmountU64(STAKE_AMOUNT - 4_000_000),
+        position: POSITION,
+        payout: None,
+        signature: None,
+    };
+    let balance_before = token_balance_of(&mut chain, cis2_token_contract_address, ALICE_ADDR);
+    unstake(&mut chain, ALICE, gona_stake_address, &param)
+        .expect("Alice should be able to unstake");
+    assert!(get_stake_query(&mut chain, gona_stake_address, POSITION).is_none());
+    assert_eq!(
+        token_balance_of(&mut chain, cis2_token_contract_address, ALICE_ADDR),
+        balance_before + STAKE_AMOUNT,
+        "the dust is paid out with the unstake"
+    );
+    assert_eq!(
+        view_staked(&mut chain, gona_stake_address, Staker::Address(ALICE_ADDR)),
+        StakedTotals {
+            staker: 10_000_000,
+            total: 10_000_000,
+        }
+    );
+}
+
+#[test]
+fn test_early_exit_penalty() {
+    let (mut chain, smart_contract_wallet, cis2_token_contract_address, gona_stake_address) =
+        initialize_chain_and_contract();
+
+    alice_deposits_cis2_tokens_and_fund_pool(
+        &mut chain,
+        smart_contract_wallet,
+        cis2_token_contract_address,
+        SERVICE_FEE_RECIPIENT_KEY,
+        gona_stake_address,
+        POOL_REWARD_AMOUNT,
+    );
+    stake_from_account(
+        &mut chain,
+        cis2_token_contract_address,
+        gona_stake_address,
+        STAKE_AMOUNT,
+    );
+    // 10% decaying over 10 days, taken from the principal for a treasury
+    let schedule = PenaltySchedule {
+        max_bps: 1000,
+        duration: Duration::from_days(10),
+        base: PenaltyBase::Principal,
+        destination: PenaltyDestination::Treasury(Payout {
+            to: Receiver::Account(CHARLIE),
+            data: AdditionalData::empty(),
+        }),
+    };
+    update_settings(
+        &mut chain,
+        gona_stake_address,
+        SettingUpdate::PenaltySchedule(Some(schedule.clone())),
+    );
+    chain
+        .tick_block_time(Duration::from_days(5))
+        .expect("days should be ticked");
+
+    let rewards = calculate_percent(WITHDRAW_STAKE_AMOUNT, WEIGHT, DECIMALS) * 5;
+    let penalty = WITHDRAW_STAKE_AMOUNT / 20;
+    let quote = quote_unstake(
+        &mut chain,
+        gona_stake_address,
+        POSITION,
+        WITHDRAW_STAKE_AMOUNT,
+    );
+    assert_eq!(
+        quote,
+        UnstakeQuote {
+            rewards,
+            owed: 0,
+            penalty,
+            fee: 0,
+            payout: WITHDRAW_STAKE_AMOUNT - penalty + rewards,
+        }
+    );
+
+    let alice_before = token_balance_of(&mut chain, cis2_token_contract_address, ALICE_ADDR);
+    let treasury_before = token_balance_of(&mut chain, cis2_token_contract_address, CHARLIE_ADDR);
+    let param = UnstakeParam {
+        amount: TokenAmountU64(WITHDRAW_STAKE_AMOUNT),
+        position: POSITION,
+        payout: None,
+        signature: None,
+    };
+    let update = unstake(&mut chain, ALICE, gona_stake_address, &param).expect("unstake");
+    assert!(staking_events(&update, gona_stake_address).contains(
+        &StakingEvent::EarlyExitPenalty {
+            position: POSITION,
+            staker: Staker::Address(ALICE_ADDR),
+            amount: TokenAmountU64(penalty),
+            treasury: Some(CHARLIE_ADDR),
+        }
+    ));
+    assert_eq!(
+        token_balance_of(&mut chain, cis2_token_contract_address, ALICE_ADDR),
+        alice_before + quote.payout
+    );
+    assert_eq!(
+        token_balance_of(&mut chain, cis2_token_contract_address, CHARLIE_ADDR),
+        treasury_before + penalty
+    );
+
+    // the same schedule on rewards, left in the reward volume
+    update_settings(
+        &mut chain,
+        gona_stake_address,
+        SettingUpdate::PenaltySchedule(Some(PenaltySchedule {
+            base: PenaltyBase::Rewards,
+            destination: PenaltyDestination::RewardVolume,
+            ..schedule
+        })),
+    );
+    let quote = quote_unstake(
+        &mut chain,
+        gona_stake_address,
+        POSITION,
+        WITHDRAW_STAKE_AMOUNT,
+    );
+    assert_eq!(quote.penalty, rewards / 20);
+    assert_eq!(quote.payout, WITHDRAW_STAKE_AMOUNT + rewards - rewards / 20);
+    unstake(&mut chain, ALICE, gona_stake_address, &param).expect("unstake");
+    assert_eq!(
+        view_reward_amount(&mut chain, gona_stake_address),
+        POOL_REWARD_AMOUNT - rewards - (rewards - rewards / 20)
+    );
+}
+
+#[test]
+fn test_early_exit_of_a_key_position_needs_its_consent() {
+    let (mut chain, smart_contract_wallet, cis2_token_contract_address, gona_stake_address) =
+        initialize_chain_and_contract();
+
+    let signing_key = ed25519_dalek::SigningKey::generate(&mut rand::thread_rng());
+    let alice_public_key = PublicKeyEd25519(signing_key.verifying_key().to_bytes());
+
+    alice_deposits_cis2_tokens_and_fund_pool(
+        &mut chain,
+        smart_contract_wallet,
+        cis2_token_contract_address,
+        alice_public_key,
+        gona_stake_address,
+        POOL_REWARD_AMOUNT,
+    );
+    stake_from_smart_wallet(
+        &mut chain,
+        smart_contract_wallet,
+        cis2_token_contract_address,
+        gona_stake_address,
+        &signing_key,
+        STAKE_AMOUNT,
+        0,
+    )
+    .expect("Should be able to stake from the smart wallet");
+    update_settings(
+        &mut chain,
+        gona_stake_address,
+        SettingUpdate::PenaltySchedule(Some(PenaltySchedule {
+            max_bps: 1000,
+            duration: Duration::from_days(10),
+            base: PenaltyBase::Principal,
+            destination: PenaltyDestination::Treasury(Payout {
+                to: Receiver::Account(CHARLIE),
+                data: AdditionalData::empty(),
+            }),
+        })),
+    );
+    chain
+        .tick_block_time(Duration::from_days(5))
+        .expect("days should be ticked");
+
+    let param = UnstakeParam {
+        amount: TokenAmountU64(WITHDRAW_STAKE_AMOUNT),
+        position: POSITION,
+        payout: None,
+        signature: None,
+    };
+    unstake(&mut chain, BOB, gona_stake_address, &param)
+        .expect_err("a stranger cannot make the key pay a penalty");
+
+    let message = PermitMessage {
+        contract_address: gona_stake_address,
+        nonce: 0,
+        timestamp: chain
+            .block_time()
+            .checked_add(Duration::from_days(1))
+            .unwrap(),
+        entry_point: OwnedEntrypointName::new_unchecked("unstake".to_string()),
+        payload: to_bytes(&param),
+    };
+    let permit_param = sign_permit(&mut chain, gona_stake_address, &signing_key, message);
+    let update = permit(&mut chain, gona_stake_address, &permit_param)
+        .expect("the key can exit early through a permit");
+    assert!(staking_events(&update, gona_stake_address).contains(
+        &StakingEvent::EarlyExitPenalty {
+            position: POSITION,
+            staker: Staker::PublicKey(alice_public_key),
+            amount: TokenAmountU64(WITHDRAW_STAKE_AMOUNT / 20),
+            treasury: Some(CHARLIE_ADDR),
+        }
+    ));
+}
+
+#[test]
+fn test_protocol_fee_on_rewards() {
+    let (mut chain, smart_contract_wallet, cis2_token_contract_address, gona_stake_address) =
+        initialize_chain_and_contract();
+
+    alice_deposits_cis2_tokens_and_fund_pool(
+        &mut chain,
+        smart_contract_wallet,
+        cis2_token_contract_address,
+        SERVICE_FEE_RECIPIENT_KEY,
+        gona_stake_address,
+        POOL_REWARD_AMOUNT,
+    );
+    stake_from_account(
+        &mut chain,
+        cis2_token_contract_address,
+        gona_stake_address,
+        STAKE_AMOUNT,
+    );
+    update_settings(
+        &mut chain,
+        gona_stake_address,
+        SettingUpdate::ProtocolFee(1000),
+    );
+    chain
+        .tick_block_time(Duration::from_days(DAYS))
+        .expect("days should be ticked");
+
+    let rewards = calculate_percent(STAKE_AMOUNT, WEIGHT, DECIMALS) * DAYS;
+    let fee = rewards / 10;
+    let balance_before = token_balance_of(&mut chain, cis2_token_contract_address, ALICE_ADDR);
+    let param = UnstakeParam {
+        amount: TokenAmountU64(STAKE_AMOUNT),
+        position: POSITION,
+        payout: None,
+        signature: None,
+    };
+    let update = unstake(&mut chain, ALICE, gona_stake_address, &param).expect("unstake");
+    assert!(
+        staking_events(&update, gona_stake_address).contains(&StakingEvent::Unstaking {
+            position: POSITION,
+            staker: Staker::Address(ALICE_ADDR),
+            amount: TokenAmountU64(STAKE_AMOUNT),
+            rewards: TokenAmountU64(rewards),
+            fee: TokenAmountU64(fee),
+            remaining: TokenAmountU64(0),
+            time: chain.block_time(),
+        })
+    );
+    assert_eq!(
+        token_balance_of(&mut chain, cis2_token_contract_address, ALICE_ADDR),
+        balance_before + STAKE_AMOUNT + rewards - fee
+    );
+    assert_eq!(
+        view_settings(&mut chain, gona_stake_address).protocol_fee,
+        ProtocolFee {
+            fee_bps: 1000,
+            fees_collected: fee,
+            fee_collector: ALICE_ADDR,
+        }
+    );
+
+    let withdraw = WithdrawFeesParam {
+        amount: TokenAmountU64(fee / 2),
+        payout: Payout {
+            to: Receiver::Account(CHARLIE),
+            data: AdditionalData::empty(),
+        },
+    };
+    withdraw_fees(&mut chain, BOB, gona_stake_address, &withdraw)
+        .expect_err("Bob is not the fee collector");
+    update_settings(
+        &mut chain,
+        gona_stake_address,
+        SettingUpdate::FeeCollector(BOB_ADDR),
+    );
+    let charlie_before = token_balance_of(&mut chain, cis2_token_contract_address, CHARLIE_ADDR);
+    withdraw_fees(&mut chain, BOB, gona_stake_address, &withdraw)
+        .expect("the fee collector withdraws fees");
+    assert_eq!(
+        token_balance_of(&mut chain, cis2_token_contract_address, CHARLIE_ADDR),
+        charlie_before + fee / 2
+    );
+    let withdraw = WithdrawFeesParam {
+        amount: TokenAmountU64(fee),
+        ..withdraw
+    };
+    withdraw_fees(&mut chain, BOB, gona_stake_address, &withdraw)
+        .expect_err("only collected fees can be withdrawn");
+}
+
+#[test]
+fn test_referrer_earns_share_of_rewards() {
+    let (mut chain, smart_contract_wallet, cis2_token_contract_address, gona_stake_address) =
+        initialize_chain_and_contract();
+
+    alice_deposits_cis2_tokens_and_fund_pool(
+        &mut chain,
+        smart_contract_wallet,
+        cis2_token_contract_address,
+        SERVICE_FEE_RECIPIENT_KEY,
+        gona_stake_address,
+        POOL_REWARD_AMOUNT,
+    );
+    update_settings(
+        &mut chain,
+        gona_stake_address,
+        SettingUpdate::ReferralRate(1000),
+    );
+    transfer_to_hook_with_data(
+        &mut chain,
+        cis2_token_contract_address,
+        gona_stake_address,
+        "stake",
+        STAKE_AMOUNT,
+        to_bytes(&Staker::Address(ALICE_ADDR)).into(),
+    )
+    .expect_err("stakers cannot refer themselves");
+    transfer_to_hook_with_data(
+        &mut chain,
+        cis2_token_contract_address,
+        gona_stake_address,
+        "stake",
+        STAKE_AMOUNT,
+        to_bytes(&Staker::Address(Address::Contract(smart_contract_wallet))).into(),
+    )
+    .expect_err("contracts cannot claim referral rewards");
+    let update = transfer_to_hook_with_data(
+        &mut chain,
+        cis2_token_contract_address,
+        gona_stake_address,
+        "stake",
+        STAKE_AMOUNT,
+        to_bytes(&Staker::Address(BOB_ADDR)).into(),
+    )
+    .expect("Should be able to stake with a referrer");
+    assert!(
+        staking_events(&update, gona_stake_address).contains(&StakingEvent::Referred {
+            position: POSITION,
+            staker: Staker::Address(ALICE_ADDR),
+            referrer: Staker::Address(BOB_ADDR),
+        })
+    );
+    chain
+        .tick_block_time(Duration::from_days(DAYS))
+        .expect("days should be ticked");
+
+    let rewards = calculate_percent(STAKE_AMOUNT, WEIGHT, DECIMALS) * DAYS;
+    let share = rewards / 10;
+    let alice_before = token_balance_of(&mut chain, cis2_token_contract_address, ALICE_ADDR);
+    let param = UnstakeParam {
+        amount: TokenAmountU64(STAKE_AMOUNT),
+        position: POSITION,
+        payout: None,
+        signature: None,
+    };
+    let update = unstake(&mut chain, ALICE, gona_stake_address, &param).expect("unstake");
+    assert!(
+        staking_events(&update, gona_stake_address).contains(&StakingEvent::ReferralReward {
+            position: POSITION,
+            referrer: Staker::Address(BOB_ADDR),
+            amount: TokenAmountU64(share),
+        })
+    );
+    assert_eq!(
+        token_balance_of(&mut chain, cis2_token_contract_address, ALICE_ADDR),
+        alice_before + STAKE_AMOUNT + rewards,
+        "the referral share does not come out of the staker's rewards"
+    );
+    assert_eq!(
+        view_referral_stats(&mut chain, gona_stake_address, Staker::Address(BOB_ADDR)),
+        ReferralStats {
+            referees: 1,
+            earned: share,
+            claimable: share,
+        }
+    );
+
+    let bob_before = token_balance_of(&mut chain, cis2_token_contract_address, BOB_ADDR);
+    claim_referral_rewards(&mut chain, BOB, gona_stake_address)
+        .expect("Bob claims his referral rewards");
+    assert_eq!(
+        token_balance_of(&mut chain, cis2_token_contract_address, BOB_ADDR),
+        bob_before + share
+    );
+    assert_eq!(
+        view_referral_stats(&mut chain, gona_stake_address, Staker::Address(BOB_ADDR)).claimable,
+        0
+    );
+    claim_referral_rewards(&mut chain, BOB, gona_stake_address)
+        .expect_err("nothing is left to claim");
+}
+
+#[test]
+fn test_staker_gating() {
+    let (mut chain, smart_contract_wallet, cis2_token_contract_address, gona_stake_address) =
+        initialize_chain_and_contract();
+
+    alice_deposits_cis2_tokens_and_fund_pool(
+        &mut chain,
+        smart_contract_wallet,
+        cis2_token_contract_address,
+        SERVICE_FEE_RECIPIENT_KEY,
+        gona_stake_address,
+        POOL_REWARD_AMOUNT,
+    );
+    update_settings(
+        &mut chain,
+        gona_stake_address,
+        SettingUpdate::StakerGating(true),
+    );
+    transfer_to_hook(
+        &mut chain,
+        cis2_token_contract_address,
+        gona_stake_address,
+        "stake",
+        STAKE_AMOUNT,
+    )
+    .expect_err("Alice is not allowlisted");
+
+    let alice = Staker::Address(ALICE_ADDR);
+    admin_update(
+        &mut chain,
+        gona_stake_address,
+        "update_staker_list",
+        &UpdateStakerListParams {
+            list: StakerList::Allowlist,
+            add: vec![alice, Staker::Address(BOB_ADDR)],
+            remove: vec![],
+        },
+    );
+    assert_eq!(
+        view_staker_access(&mut chain, gona_stake_address, alice),
+        StakerAccess {
+            allowlisted: true,
+            denylisted: false,
+            can_stake: true,
+        }
+    );
+    stake_from_account(
+        &mut chain,
+        cis2_token_contract_address,
+        gona_stake_address,
+        STAKE_AMOUNT,
+    );
+
+    // receiving a position or receipts is staking too
+    transfer_stake_tokens(
+        &mut chain,
+        ALICE,
+        gona_stake_address,
+        position_token_id(POSITION),
+        Receiver::Account(CHARLIE),
+        1,
+    )
+    .expect_err("Charlie is not allowlisted");
+    transfer_to_hook(
+        &mut chain,
+        cis2_token_contract_address,
+        gona_stake_address,
+        "stake_liquid",
+        STAKE_AMOUNT,
+    )
+    .expect("Alice stakes in the liquid pool");
+    transfer_receipts(
+        &mut chain,
+        ALICE,
+        gona_stake_address,
+        Receiver::Account(CHARLIE),
+        1000,
+    )
+    .expect_err("Charlie is not allowlisted");
+    transfer_receipts(
+        &mut chain,
+        ALICE,
+        gona_stake_address,
+        Receiver::Account(BOB),
+        1000,
+    )
+    .expect("Bob is allowlisted");
+    let unstake_liquid = Receiver::Contract(
+        gona_stake_address,
+        OwnedEntrypointName::new_unchecked("unstake_liquid".to_owned()),
+    );
+    transfer_receipts(
+        &mut chain,
+        ALICE,
+        gona_stake_address,
+        unstake_liquid.clone(),
+        1000,
+    )
+    .expect("redeeming receipts is not staking");
+
+    admin_update(
+        &mut chain,
+        gona_stake_address,
+        "update_staker_list",
+        &UpdateStakerListParams {
+            list: StakerList::Denylist,
+            add: vec![alice],
+            remove: vec![],
+        },
+    );
+    assert!(
+        !view_staker_access(&mut chain, gona_stake_address, alice).can_stake,
+        "the denylist overrides the allowlist"
+    );
+    transfer_to_hook(
+        &mut chain,
+        cis2_token_contract_address,
+        gona_stake_address,
+        "stake",
+        STAKE_AMOUNT,
+    )
+    .expect_err("Alice is denylisted");
+
+    let param = UnstakeParam {
+        amount: TokenAmountU64(STAKE_AMOUNT),
+        position: POSITION,
+        payout: None,
+        signature: None,
+    };
+    unstake(&mut chain, ALICE, gona_stake_address, &param)
+        .expect("blocked stakers can still unstake");
+    transfer_receipts(&mut chain, ALICE, gona_stake_address, unstake_liquid, 1000)
+        .expect("blocked stakers can still redeem their receipts");
+}
+
+#[test]
+fn test_events_track_every_state_change() {
+    let (mut chain, smart_contract_wallet, cis2_token_contract_address, gona_stake_address) =
+        initialize_chain_and_contract();
+
+    alice_deposits_cis2_tokens_and_fund_pool(
+        &mut chain,
+        smart_contract_wallet,
+        cis2_token_contract_address,
+        SERVICE_FEE_RECIPIENT_KEY,
+        gona_stake_address,
+        POOL_REWARD_AMOUNT,
+    );
+    stake_from_account(
+        &mut chain,
+        cis2_token_contract_address,
+        gona_stake_address,
+        STAKE_AMOUNT,
+    );
+
+    let update = admin_update(&mut chain, gona_stake_address, "change_weight", &WEIGHT);
+    assert_eq!(
+        staking_events(&update, gona_stake_address),
+        [StakingEvent::WeightChanged { weight: WEIGHT }]
+    );
+    let setting = SettingUpdate::ProtocolFee(500);
+    let update = admin_update(&mut chain, gona_stake_address, "update_settings", &setting);
+    assert_eq!(
+        staking_events(&update, gona_stake_address),
+        [StakingEvent::SettingUpdated(setting)]
+    );
+    let update = admin_update(
+        &mut chain,
+        gona_stake_address,
+        "set_auto_compound",
+        &SetAutoCompoundParam {
+            position: POSITION,
+            enabled: true,
+        },
+    );
+    assert_eq!(
+        staking_events(&update, gona_stake_address),
+        [StakingEvent::AutoCompoundSet {
+            position: POSITION,
+            enabled: true,
+        }]
+    );
+
+    chain
+        .tick_block_time(Duration::from_days(DAYS))
+        .expect("days should be ticked");
+    let rewards = calculate_percent(WITHDRAW_STAKE_AMOUNT, WEIGHT, DECIMALS) * DAYS;
+    let fee = rewards / 20;
+    let reward_volume = view_reward_amount(&mut chain, gona_stake_address);
+    let param = UnstakeParam {
+        amount: TokenAmountU64(WITHDRAW_STAKE_AMOUNT),
+        position: POSITION,
+        payout: None,
+        signature: None,
+    };
+    let update = unstake(&mut chain, ALICE, gona_stake_address, &param).expect("unstake");
+    let events = staking_events(&update, gona_stake_address);
+    assert!(events.contains(&StakingEvent::Unstaking {
+        position: POSITION,
+        staker: Staker::Address(ALICE_ADDR),
+        amount: TokenAmountU64(WITHDRAW_STAKE_AMOUNT),
+        rewards: TokenAmountU64(rewards),
+        fee: TokenAmountU64(fee),
+        remaining: TokenAmountU64(STAKE_AMOUNT - WITHDRAW_STAKE_AMOUNT),
+        time: chain.block_time(),
+    }));
+    assert_eq!(
+        events.last(),
+        Some(&StakingEvent::RewardVolumeChanged {
+            reward_volume: TokenAmountU64(reward_volume - rewards),
+        })
+    );
+
+    // a new accrual granularity first pays the liquid pool what it earned
+    transfer_to_hook(
+        &mut chain,
+        cis2_token_contract_address,
+        gona_stake_address,
+        "stake_liquid",
+        STAKE_AMOUNT,
+    )
+    .expect("Alice stakes in the liquid pool");
+    chain
+        .tick_block_time(Duration::from_days(1))
+        .expect("a day should be ticked");
+    let setting = SettingUpdate::AccrualGranularity(Duration::from_hours(1));
+    let update = admin_update(&mut chain, gona_stake_address, "update_settings", &setting);
+    let reward_volume = view_reward_amount(&mut chain, gona_stake_address);
+    assert_eq!(
+        staking_events(&update, gona_stake_address),
+        [
+            StakingEvent::SettingUpdated(setting),
+            StakingEvent::RewardVolumeChanged {
+                reward_volume: TokenAmountU64(reward_volume),
+            }
+        ]
+    );
+
+    // so does a new weight
+    chain
+        .tick_block_time(Duration::from_days(1))
+        .expect("a day should be ticked");
+    let update = admin_update(&mut chain, gona_stake_address, "change_weight", &WEIGHT);
+    let reward_volume = view_reward_amount(&mut chain, gona_stake_address);
+    assert_eq!(
+        staking_events(&update, gona_stake_address),
+        [
+            StakingEvent::WeightChanged { weight: WEIGHT },
+            StakingEvent::RewardVolumeChanged {
+                reward_volume: TokenAmountU64(reward_volume),
+            }
+        ]
+    );
+
+    let update = admin_update(
+        &mut chain,
+        gona_stake_address,
+        "update_settings",
+        &SettingUpdate::Admin(BOB_ADDR),
+    );
+    assert_eq!(
+        staking_events(&update, gona_stake_address),
+        [StakingEvent::AdminChanged {
+            previous: ALICE_ADDR,
+            admin: BOB_ADDR,
+        }]
+    );
+    chain
+        .contract_update(
+            SIGNER,
+            ALICE,
+            ALICE_ADDR,
+            Energy::from(10000),
+            UpdateContractPayload {
+                amount: Amount::zero(),
+                receive_name: OwnedReceiveName::new_unchecked("gona_stake.set_paused".to_string()),
+                address: gona_stake_address,
+                message: OwnedParameter::empty(),
+            },
+        )
+        .expect_err("Alice is no longer the admin");
+    let update = chain
+        .contract_update(
+            SIGNER,
+            BOB,
+            BOB_ADDR,
+            Energy::from(10000),
+            UpdateContractPayload {
+                amount: Amount::zero(),
+                receive_name: OwnedReceiveName::new_unchecked("gona_stake.set_paused".to_string()),
+                address: gona_stake_address,
+                message: OwnedParameter::empty(),
+            },
+        )
+        .expect("Bob is the admin");
+    assert_eq!(
+        staking_events(&update, gona_stake_address),
+        [StakingEvent::PauseChanged { paused: true }]
+    );
+}
+
+#[test]
+fn test_mirror_rebuilds_state_from_events() {
+    let (mut chain, smart_contract_wallet, cis2_token_contract_address, gona_stake_address) =
+        initialize_chain_and_contract();
+    let mut mirror = Mirror::new(
+        &InitParam {
+            admin: ALICE_ADDR,
+            decimals: DECIMALS,
+            token_address: cis2_token_contract_address,
+            weight: WEIGHT,
+            smart_wallets: vec![smart_contract_wallet],
+            limits: STAKE_LIMITS,
+        },
+        chain.block_time(),
+    );
+    let mut events = Vec::new();
+
+    alice_deposits_cis2_tokens_and_fund_pool(
+        &mut chain,
+        smart_contract_wallet,
+        cis2_token_contract_address,
+        SERVICE_FEE_RECIPIENT_KEY,
+        gona_stake_address,
+        0,
+    );
+    for entrypoint in ["depositCis2Tokens", "stake", "stake"] {
+        let amount = match entrypoint {
+            "stake" => STAKE_AMOUNT,
+            _ => POOL_REWARD_AMOUNT,
+        };
+        let update = transfer_to_hook(
+            &mut chain,
+            cis2_token_contract_address,
+            gona_stake_address,
+            entrypoint,
+            amount,
+        )
+        .expect("transfer");
+        record_events(&mut events, &chain, &update, gona_stake_address);
+    }
+    let enable = SetAutoCompoundParam {
+        position: POSITION,
+        enabled: true,
+    };
+    let update = admin_update(&mut chain, gona_stake_address, "set_auto_compound", &enable);
+    record_events(&mut events, &chain, &update, gona_stake_address);
+    let update = admin_update(
+        &mut chain,
+        gona_stake_address,
+        "change_weight",
+        &(WEIGHT * 2),
+    );
+    record_events(&mut events, &chain, &update, gona_stake_address);
+
+    chain
+        .tick_block_time(Duration::from_days(DAYS))
+        .expect("days should be ticked");
+    let update = compound(&mut chain, gona_stake_address, vec![POSITION]).expect("compound");
+    record_events(&mut events, &chain, &update, gona_stake_address);
+    let param = UnstakeParam {
+        amount: TokenAmountU64(WITHDRAW_STAKE_AMOUNT),
+        position: POSITION + 1,
+        payout: None,
+        signature: None,
+    };
+    let update = unstake(&mut chain, ALICE, gona_stake_address, &param).expect("unstake");
+    record_events(&mut events, &chain, &update, gona_stake_address);
+    let update = transfer_stake_tokens(
+        &mut chain,
+        ALICE,
+        gona_stake_address,
+        position_token_id(POSITION),
+        Receiver::Account(BOB),
+        1,
+    )
+    .expect("Alice transfers her position to Bob");
+    record_events(&mut events, &chain, &update, gona_stake_address);
+    let update = unstake(&mut chain, ALICE, gona_stake_address, &param).expect("unstake");
+    record_events(&mut events, &chain, &update, gona_stake_address);
+
+    mirror.apply_all(events.iter().map(|(time, event)| (*time, event)));
+    let reward_volume = view_reward_amount(&mut chain, gona_stake_address);
+    let inconsistencies = mirror
+        .check(
+            |position| Ok::<_, ()>(get_stake_query(&mut chain, gona_stake_address, position)),
+            reward_volume,
+        )
+        .expect("check");
+    assert_eq!(inconsistencies, []);
+
+    let bob_position = mirror.positions[&POSITION].clone();
+    assert_eq!(bob_position.owner, Staker::Address(BOB_ADDR));
+    assert!(!mirror.positions.contains_key(&(POSITION + 1)));
+    assert_eq!(
+        mirror.balances.get(&Staker::Address(BOB_ADDR)),
+        Some(&bob_position.amount)
+    );
+    assert_eq!(mirror.balances.get(&Staker::Address(ALICE_ADDR)), None);
+    assert_eq!(mirror.weight(), WEIGHT * 2);
+    assert_eq!(mirror.weight_history.len(), 2);
+
+    // a missed deposit shows up in the check
+    let update = transfer_to_hook(
+        &mut chain,
+        cis2_token_contract_address,
+        gona_stake_address,
+        "depositCis2Tokens",
+        1000,
+    )
+    .expect("deposit");
+    assert!(!staking_events(&update, gona_stake_address).is_empty());
+    let inconsistencies = mirror
+        .check(
+            |position| Ok::<_, ()>(get_stake_query(&mut chain, gona_stake_address, position)),
+            reward_volume + 1000,
+        )
+        .expect("check");
+    assert_eq!(
+        inconsistencies,
+        [Inconsistency::RewardVolume {
+            mirrored: reward_volume,
+            on_chain: reward_volume + 1000,
+        }]
+    );
+}
+
+/// With the `serde` feature, the contract types round-trip through JSON.
+#[cfg(feature = "serde")]
+#[test]
+fn test_types_round_trip_through_json() {
+    fn round_trip<T: SerdeSerialize + serde::de::DeserializeOwned>(value: &T) -> T {
+        let json = serde_json::to_string(value).expect("Serialize to JSON");
+        serde_json::from_str(&json).expect("Deserialize from JSON")
+    }
+
+    let init = InitParam {
+        admin: ALICE_ADDR,
+        decimals: DECIMALS,
+        token_address: ContractAddress::new(7000, 0),
+        weight: WEIGHT,
+        smart_wallets: vec![ContractAddress::new(7001, 0)],
+        limits: STAKE_LIMITS,
+    };
+    assert_eq!(to_bytes(&round_trip(&init)), to_bytes(&init));
+
+    let entry = StakeEntry {
+        owner: Staker::PublicKey(SERVICE_FEE_RECIPIENT_KEY),
+        amount: TokenAmountU64(STAKE_AMOUNT),
+        time_of_stake: Timestamp::from_timestamp_millis(1000),
+        token_id: TOKEN_ID,
+        pending_rewards: 25,
+        smart_wallet: Some(ContractAddress::new(7001, 0)),
+        auto_compound: true,
+        reward_epoch: None,
+        referrer: Some(Staker::Address(BOB_ADDR)),
+    };
+    assert_eq!(round_trip(&entry), entry);
+    assert_eq!(
+        round_trip(&SettingUpdate::Admin(BOB_ADDR)),
+        SettingUpdate::Admin(BOB_ADDR)
+    );
+
+    let events = [
+        StakingEvent::Staked {
+            position: 1,
+            staker: Staker::Address(ALICE_ADDR),
+            amount: TokenAmountU64(STAKE_AMOUNT),
+            time: Timestamp::from_timestamp_millis(1000),
+        },
+        StakingEvent::Transfer(TransferEvent {
+            token_id: position_token_id(1),
+            amount: TokenAmountU64(1),
+            from: ALICE_ADDR,
+            to: BOB_ADDR,
+        }),
+        StakingEvent::TokenMetadata(TokenMetadataEvent {
+            token_id: position_token_id(1),
+            metadata_url: MetadataUrl {
+                url: "https://example.com/position/1".to_string(),
+                hash: None,
+            },
+        }),
+    ];
+    for event in &events {
+        assert_eq!(&round_trip(event), event);
+    }
+}
+
+/// Setup chain and contract.
+///
+/// Also creates the three accounts, Alice, Bob, and Charlie.
+///
+/// Alice is the owner of the contract.
+fn initialize_chain_and_contract() -> (Chain, ContractAddress, ContractAddress, ContractAddress) {
+    let mut chain = Chain::new();
+
+    // Create some accounts on the chain.
+    chain.create_account(Account::new(ALICE, ACC_INITIAL_BALANCE));
+    chain.create_account(Account::new(BOB, ACC_INITIAL_BALANCE));
+    chain.create_account(Account::new(CHARLIE, ACC_INITIAL_BALANCE));
+
+    let metadata = SetMetadataUrlParams {
+        url: "https://www.example.come".to_string(),
+        hash: None,
+    };
+    // Load and deploy cis2 token module.
+    let module = module_load_v1("tests/cis2_token/module.wasm.v1").expect("Module exists");
+    let deployment = chain
+        .module_deploy_v1_debug(SIGNER, ALICE, module, true)
+        .expect("Deploy valid module");
+
+    // Initialize the token contract.
+    let cis2_token_contract_init = chain
+        .contract_init(
+            SIGNER,
+            ALICE,
+            Energy::from(10000),
+            InitContractPayload {
+                amount: Amount::zero(),
+                mod_ref: deployment.module_reference,
+                init_name: OwnedContractName::new_unchecked("init_gona_token".to_string()),
+                param: OwnedParameter::from_serial(&metadata).expect("Token amount params"),
+            },
+        )
+        .expect("Initialize contract");
+
+    // Load and deploy the smart wallet module.
+    let module = module_load_v1("tests/chaperone/module.wasm.v1").expect("Module exists");
+    let deployment = chain
+        .module_deploy_v1_debug(SIGNER, ALICE, module, true)
+        .expect("Deploy valid module");
+
+    // Initialize the smart contract wallet.
+    let smart_contract_wallet_init = chain
+        .contract_init(
+            SIGNER,
+            ALICE,
+            Energy::from(10000),
+            InitContractPayload {
+                amount: Amount::zero(),
+                mod_ref: deployment.module_reference,
+                init_name: OwnedContractName::new_unchecked(
+                    "init_smart_contract_wallet".to_string(),
+                ),
+                param: OwnedParameter::empty(),
+            },
+        )
+        .expect("Initialize contract");
+
+    // Load and deploy the gona stake module.
+    let module = module_load_v1("dist/stake.wasm.v1").expect("Module exists");
+    let deployment = chain
+        .module_deploy_v1_debug(SIGNER, ALICE, module, true)
+        .expect("Deploy valid module");
+
+    // Initialize the smart contract wallet.
+    let param = InitParam {
+        admin: Address::Account(ALICE),
+        decimals: DECIMALS,
+        token_address: cis2_token_contract_init.contract_address,
+        weight: WEIGHT,
+        smart_wallets: vec![smart_contract_wallet_init.contract_address],
+        limits: STAKE_LIMITS,
+    };
+    let param = OwnedParameter::from_serial(&param).unwrap();
+    let gona_stake_init = chain
+        .contract_init(
+            SIGNER,
+            ALICE,
+            Energy::from(10000),
+            InitContractPayload {
+                amount: Amount::zero(),
+                mod_ref: deployment.module_reference,
+                init_name: OwnedContractName::new_unchecked("init_gona_stake".to_string()),
+                param,
+            },
+        )
+        .expect("Initialize contract");
+
+    (
+        chain,
+        smart_contract_wallet_init.contract_address,
+        cis2_token_contract_init.contract_address,
+        gona_stake_init.contract_address,
+    )
+}
+
+fn alice_deposits_cis2_tokens_and_fund_pool(
+    chain: &mut Chain,
+    smart_contract_wallet: ContractAddress,
+    cis2_token_contract_address: ContractAddress,
+    alice_public_key: PublicKeyEd25519,
+    gona_stake_address: ContractAddress,
+    pool_amount: u64,
+) {
+    let mint_param: MintParam = MintParam {
+        owner: Address::Account(ALICE),
+        token_id: TOKEN_ID,
+        amount: TokenAmountU64(AIRDROP_TOKEN_AMOUNT),
+    };
+
+    //Deposit tokens.
+    let _update = chain
+        .contract_update(
+            SIGNER,
+            ALICE,
+            ALICE_ADDR,
+            Energy::from(10000),
+            UpdateContractPayload {
+                amount: Amount::zero(),
+                receive_name: OwnedReceiveName::new_unchecked("gona_token.mint".to_string()),
+                address: cis2_token_contract_address,
+                message: OwnedParameter::from_serial(&mint_param).expect("Mint cis2 tokens params"),
+            },
+        )
+        .expect("Should be able to deposit cis2 tokens");
+
+    // // Create a Transfer instance
+    let transfer_payload = concordium_cis2::Transfer {
+        token_id: TOKEN_ID,
+        amount: TokenAmountU64(ALICE_KEY_AMOUNT),
+        to: Receiver::Contract(
+            smart_contract_wallet,
+            OwnedEntrypointName::new_unchecked("depositCis2Tokens".into()),
+        ),
+        from: ALICE_ADDR,
+        data: AdditionalData::from(to_bytes(&alice_public_key)),
+    };
+    // Create a Transfer instance
+    let transfer_pool_payload = concordium_cis2::Transfer {
+        token_id: TOKEN_ID,
+        amount: TokenAmountU64(pool_amount),
+        to: Receiver::Contract(
+            gona_stake_address,
+            OwnedEntrypointName::new_unchecked("depositCis2Tokens".into()),
+        ),
+        from: ALICE_ADDR,
+        data: AdditionalData::empty(),
+    };
+    let payload = TransferParams::from(vec![transfer_payload, transfer_pool_payload]);
+    // Deposit tokens.
+    let _update = chain
+        .contract_update(
+            SIGNER,
+            ALICE,
+            ALICE_ADDR,
+            Energy::from(10000),
+            UpdateContractPayload {
+                amount: Amount::zero(),
+                receive_name: OwnedReceiveName::new_unchecked("gona_token.transfer".to_string()),
+                address: cis2_token_contract_address,
+                message: OwnedParameter::from_serial(&payload).expect("Mint cis2 tokens params"),
+            },
+        )
+        .expect("Should be able to deposit cis2 tokens");
+}
+
+fn get_stake_query(
+    chain: &mut Chain,
+    gona_stake: ContractAddress,
+    position: PositionId,
+) -> StakeQuery {
+    let invoke = chain
+        .contract_invoke(
+            ALICE,
+            ALICE_ADDR,
+            Energy::from(10000),
+            UpdateContractPayload {
+                amount: Amount::zero(),
+                receive_name: OwnedReceiveName::new_unchecked(
+                    "gona_stake.get_stake_info".to_string(),
+                ),
+                address: gona_stake,
+                message: OwnedParameter::from_serial(&position).expect("Damn, wth happened"),
+            },
+        )
+        .expect("Invoke Stake Query");
+    let sq: StakeQuery = invoke
+        .parse_return_value()
+        .expect("Damn, the hell happened");
+    sq
+}
+
+fn view_reward_amount(chain: &mut Chain, gona_stake: ContractAddress) -> u64 {
+    let invoke = chain
+        .contract_invoke(
+            ALICE,
+            ALICE_ADDR,
+            Energy::from(10000),
+            UpdateContractPayload {
+                amount: Amount::zero(),
+                receive_name: OwnedReceiveName::new_unchecked(
+                    "gona_stake.view_reward_volume".to_string(),
+                ),
+                address: gona_stake,
+                message: OwnedParameter::empty(),
+            },
+        )
+        .expect("Invoke Stake Query");
+    let res: u64 = invoke
+        .parse_return_value()
+        .expect("Damn, the hell happened");
+    res
+}
+
+fn calculate_reward(
+    chain: &mut Chain,
+    gona_stake: ContractAddress,
+    position: PositionId,
+) -> RewardResult {
+    let invoke = chain
+        .contract_invoke(
+            ALICE,
+            ALICE_ADDR,
+            Energy::from(10000),
+            UpdateContractPayload {
+                amount: Amount::zero(),
+                receive_name: OwnedReceiveName::new_unchecked(
+                    "gona_stake.calculate_rewards".to_string(),
+                ),
+                address: gona_stake,
+                message: OwnedParameter::from_serial(&position)
+                    .expect("Position could not be serialized"),
+            },
+        )
+        .expect("Invoke Stake Query");
+    let res: RewardResult = invoke
+        .parse_return_value()
+        .expect("Damn, the hell happened");
+    res
+}
+
+fn calculate_percent(amount: u64, weight: u32, decimals: u8) -> u64 {
+    (amount * (weight as u64)) / (100 * (10_i32.pow(decimals as u32) as u64))
+}
+
+fn release_stake(
+    chain: &mut Chain,
+    gona_stake: ContractAddress,
+    position: PositionId,
+    signing_key: &ed25519_dalek::SigningKey,
+    amount: u64,
+) -> ContractInvokeSuccess {
+    let param = UnstakeParam {
+        amount: TokenAmountU64(amount),
+        position,
+        payout: None,
+        signature: None,
+    };
+    permit_as(chain, gona_stake, signing_key, "unstake", to_bytes(&param))
+        .expect("the key should be able to unstake")
+}
+
+/// Stakes `amount` of tokens held by `signing_key` in the smart wallet by
+/// withdrawing them to the `stake` hook of the gona stake contract.
+fn stake_from_smart_wallet(
+    chain: &mut Chain,
+    smart_contract_wallet: ContractAddress,
+    cis2_token_contract_address: ContractAddress,
+    gona_stake_address: ContractAddress,
+    signing_key: &ed25519_dalek::SigningKey,
+    amount: u64,
+    nonce: u64,
+) -> Result<ContractInvokeSuccess, ContractInvokeError> {
+    withdraw_from_smart_wallet(
+        chain,
+        smart_contract_wallet,
+        cis2_token_contract_address,
+        Receiver::Contract(
+            gona_stake_address,
+            OwnedEntrypointName::new_unchecked("stake".to_owned()),
+        ),
+        signing_key,
+        amount,
+        nonce,
+    )
+}
+
+/// Withdraws `amount` of the tokens of `cis2_token_contract_address` held by
+/// `signing_key` in the smart wallet to `to`, with the key as data.
+fn withdraw_from_smart_wallet(
+    chain: &mut Chain,
+    smart_contract_wallet: ContractAddress,
+    cis2_token_contract_address: ContractAddress,
+    to: Receiver,
+    signing_key: &ed25519_dalek::SigningKey,
+    amount: u64,
+    nonce: u64,
+) -> Result<ContractInvokeSuccess, ContractInvokeError> {
+    use ed25519_dalek::Signer;
+
+    let public_key = PublicKeyEd25519(signing_key.verifying_key().to_bytes());
+    let message = WithdrawMessage {
+        entry_point: OwnedEntrypointName::new_unchecked("withdrawCis2Tokens".to_string()),
+        expiry_time: Timestamp::now(),
+        nonce,
+        service_fee_recipient: SERVICE_FEE_RECIPIENT_KEY,
+        simple_withdraws: vec![Withdraw {
+            to,
+            withdraw_amount: TokenAmount {
+                token_amount: TokenAmountU256(U256::from(amount)),
+                token_id: TOKEN_ID,
+                cis2_token_contract_address,
+            },
+            data: AdditionalData::from(to_bytes(&public_key)),
+        }],
+        service_fee_amount: TokenAmount {
+            token_amount: TokenAmountU256(0.into()),
+            token_id: TOKEN_ID,
+            cis2_token_contract_address,
+        },
+    };
+
+    // Get the message hash to be signed.
+    let invoke = chain
+        .contract_invoke(
+            ALICE,
+            ALICE_ADDR,
+            Energy::from(10000),
+            UpdateContractPayload {
+                amount: Amount::zero(),
+                address: smart_contract_wallet,
+                receive_name: OwnedReceiveName::new_unchecked(
+                    "smart_contract_wallet.getCis2WithdrawMessageHash".to_string(),
+                ),
+                message: OwnedParameter::from_serial(&message)
+                    .expect("Should be a valid inut parameter"),
+            },
+        )
+        .expect("Should be able to query getCis2WithdrawMessageHash");
+
+    let withdraw_param = WithdrawParameter {
+        withdraws: vec![WithdrawBatch {
+            signer: public_key,
+            signature: SignatureEd25519(signing_key.sign(&invoke.return_value).to_bytes()),
+            message,
+        }],
+    };
+
+    chain.contract_update(
+        SIGNER,
+        CHARLIE,
+        CHARLIE_ADDR,
+        Energy::from(10000),
+        UpdateContractPayload {
+            amount: Amount::zero(),
+            receive_name: OwnedReceiveName::new_unchecked(
+                "smart_contract_wallet.withdrawCis2Tokens".to_string(),
+            ),
+            address: smart_contract_wallet,
+            message: OwnedParameter::from_serial(&withdraw_param)
+                .expect("Withdraw cis2 tokens params"),
+        },
+    )
+}
+
+/// Alice tops up the reward pool with `amount` of her tokens.
+fn fund_reward_pool(
+    chain: &mut Chain,
+    cis2_token_contract_address: ContractAddress,
+    gona_stake_address: ContractAddress,
+    amount: u64,
+) {
+    let transfer_payload = concordium_cis2::Transfer {
+        token_id: TOKEN_ID,
+        amount: TokenAmountU64(amount),
+        to: Receiver::Contract(
+            gona_stake_address,
+            OwnedEntrypointName::new_unchecked("depositCis2Tokens".into()),
+        ),
+        from: ALICE_ADDR,
+        data: AdditionalData::empty(),
+    };
+    let payload = TransferParams::from(vec![transfer_payload]);
+    chain
+        .contract_update(
+            SIGNER,
+            ALICE,
+            ALICE_ADDR,
+            Energy::from(10000),
+            UpdateContractPayload {
+                amount: Amount::zero(),
+                receive_name: OwnedReceiveName::new_unchecked("gona_token.transfer".to_string()),
+                address: cis2_token_contract_address,
+                message: OwnedParameter::from_serial(&payload).expect("Transfer params"),
+            },
+        )
+        .expect("Should be able to fund the reward pool");
+}
+
+fn claim_rewards(
+    chain: &mut Chain,
+    gona_stake: ContractAddress,
+    signing_key: &ed25519_dalek::SigningKey,
+    position: PositionId,
+) -> Result<ContractInvokeSuccess, ContractInvokeError> {
+    permit_as(
+        chain,
+        gona_stake,
+        signing_key,
+        "claim_rewards",
+        to_bytes(&position),
+    )
+}
+
+/// Collects the staking events logged by the gona stake contract.
+fn staking_events(
+    update: &ContractInvokeSuccess,
+    gona_stake: ContractAddress,
+) -> Vec<StakingEvent> {
+    update
+        .events()
+        .filter(|(address, _)| *address == gona_stake)
+        .flat_map(|(_, events)| events.iter())
+        .map(|event| event.parse().expect("Should be a staking event"))
+        .collect()
+}
+
+fn emergency_withdraw(
+    chain: &mut Chain,
+    gona_stake: ContractAddress,
+    position: PositionId,
+) -> Result<ContractInvokeSuccess, ContractInvokeError> {
+    chain.contract_update(
+        SIGNER,
+        ALICE,
+        ALICE_ADDR,
+        Energy::from(10000),
+        UpdateContractPayload {
+            amount: Amount::zero(),
+            receive_name: OwnedReceiveName::new_unchecked(
+                "gona_stake.emergency_withdraw".to_string(),
+            ),
+            address: gona_stake,
+            message: OwnedParameter::from_serial(&position).expect("Position params"),
+        },
+    )
+}
+
+/// Calls an admin entrypoint of the gona stake contract as Alice.
+fn admin_update<P: Serial>(
+    chain: &mut Chain,
+    gona_stake: ContractAddress,
+    entrypoint: &str,
+    param: &P,
+) -> ContractInvokeSuccess {
+    chain
+        .contract_update(
+            SIGNER,
+            ALICE,
+            ALICE_ADDR,
+            Energy::from(10000),
+            UpdateContractPayload {
+                amount: Amount::zero(),
+                receive_name: OwnedReceiveName::new_unchecked(format!("gona_stake.{entrypoint}")),
+                address: gona_stake,
+                message: OwnedParameter::from_serial(param).expect("Admin params"),
+            },
+        )
+        .expect("Admin update should succeed")
+}
+
+/// Alice stakes `amount` straight from her account with a plain transfer.
+fn stake_from_account(
+    chain: &mut Chain,
+    cis2_token_contract_address: ContractAddress,
+    gona_stake_address: ContractAddress,
+    amount: u64,
+) {
+    transfer_to_hook(
+        chain,
+        cis2_token_contract_address,
+        gona_stake_address,
+        "stake",
+        amount,
+    )
+    .expect("Should be able to transfer from an account");
+}
+
+/// Alice transfers `amount` from her account to `entrypoint` of the gona
+/// stake contract without data.
+fn transfer_to_hook(
+    chain: &mut Chain,
+    cis2_token_contract_address: ContractAddress,
+    gona_stake_address: ContractAddress,
+    entrypoint: &str,
+    amount: u64,
+) -> Result<ContractInvokeSuccess, ContractInvokeError> {
+    transfer_to_hook_with_data(
+        chain,
+        cis2_token_contract_address,
+        gona_stake_address,
+        entrypoint,
+        amount,
+        AdditionalData::empty(),
+    )
+}
+
+fn transfer_to_hook_with_data(
+    chain: &mut Chain,
+    cis2_token_contract_address: ContractAddress,
+    gona_stake_address: ContractAddress,
+    entrypoint: &str,
+    amount: u64,
+    data: AdditionalData,
+) -> Result<ContractInvokeSuccess, ContractInvokeError> {
+    let transfer_payload = concordium_cis2::Transfer {
+        token_id: TOKEN_ID,
+        amount: TokenAmountU64(amount),
+        to: Receiver::Contract(
+            gona_stake_address,
+            OwnedEntrypointName::new_unchecked(entrypoint.into()),
+        ),
+        from: ALICE_ADDR,
+        data,
+    };
+    let payload = TransferParams::from(vec![transfer_payload]);
+    chain.contract_update(
+        SIGNER,
+        ALICE,
+        ALICE_ADDR,
+        Energy::from(10000),
+        UpdateContractPayload {
+            amount: Amount::zero(),
+            receive_name: OwnedReceiveName::new_unchecked("gona_token.transfer".to_string()),
+            address: cis2_token_contract_address,
+            message: OwnedParameter::from_serial(&payload).expect("Transfer params"),
+        },
+    )
+}
+
+fn unstake(
+    chain: &mut Chain,
+    sender: AccountAddress,
+    gona_stake: ContractAddress,
+    param: &UnstakeParam,
+) -> Result<ContractInvokeSuccess, ContractInvokeError> {
+    chain.contract_update(
+        SIGNER,
+        sender,
+        Address::Account(sender),
+        Energy::from(10000),
+        UpdateContractPayload {
+            amount: Amount::zero(),
+            receive_name: OwnedReceiveName::new_unchecked("gona_stake.unstake".to_string()),
+            address: gona_stake,
+            message: OwnedParameter::from_serial(param).expect("Unstake params"),
+        },
+    )
+}
+
+fn token_balance_of(
+    chain: &mut Chain,
+    cis2_token_contract_address: ContractAddress,
+    owner: Address,
+) -> u64 {
+    let query = BalanceOfQueryParams {
+        queries: vec![BalanceOfQuery {
+            token_id: TOKEN_ID,
+            address: owner,
+        }],
+    };
+    let invoke = chain
+        .contract_invoke(
+            ALICE,
+            ALICE_ADDR,
+            Energy::from(10000),
+            UpdateContractPayload {
+                amount: Amount::zero(),
+                receive_name: OwnedReceiveName::new_unchecked("gona_token.balanceOf".to_string()),
+                address: cis2_token_contract_address,
+                message: OwnedParameter::from_serial(&query).expect("Balance query params"),
+            },
+        )
+        .expect("Invoke balanceOf");
+    let balances: BalanceOfQueryResponse<TokenAmountU64> =
+        invoke.parse_return_value().expect("Balance response");
+    balances.0[0].0
+}
 
-    // Initialize the smart contract wallet.
-    let smart_contract_wallet_init = chain
+/// Initializes another instance of the smart wallet.
+fn init_smart_contract_wallet(chain: &mut Chain) -> ContractAddress {
+    let module = module_load_v1("tests/chaperone/module.wasm.v1").expect("Module exists");
+    chain
         .contract_init(
             SIGNER,
             ALICE,
             Energy::from(10000),
             InitContractPayload {
                 amount: Amount::zero(),
-                mod_ref: deployment.module_reference,
+                mod_ref: module.get_module_ref(),
                 init_name: OwnedContractName::new_unchecked(
                     "init_smart_contract_wallet".to_string(),
                 ),
                 param: OwnedParameter::empty(),
             },
         )
-        .expect("Initialize contract");
+        .expect("Initialize contract")
+        .contract_address
+}
 
-    // Load and deploy the gona stake module.
-    let module = module_load_v1("dist/stake.wasm.v1").expect("Module exists");
-    let deployment = chain
-        .module_deploy_v1_debug(SIGNER, ALICE, module, true)
-        .expect("Deploy valid module");
+#[derive(Serialize, SchemaType)]
+pub struct Cis2TokensBalanceOfQuery {
+    pub token_id: TokenIdVec,
+    pub cis2_token_contract_address: ContractAddress,
+    pub public_key: PublicKeyEd25519,
+}
 
-    // Initialize the smart contract wallet.
-    let param = InitParam {
-        admin: Address::Account(ALICE),
-        decimals: DECIMALS,
-        token_address: cis2_token_contract_init.contract_address,
-        weight: WEIGHT,
-        smart_wallet: smart_contract_wallet_init.contract_address,
-    };
-    let param = OwnedParameter::from_serial(&param).unwrap();
-    let gona_stake_init = chain
-        .contract_init(
-            SIGNER,
-            ALICE,
-            Energy::from(10000),
-            InitContractPayload {
-                amount: Amount::zero(),
-                mod_ref: deployment.module_reference,
-                init_name: OwnedContractName::new_unchecked("init_gona_stake".to_string()),
-                param,
-            },
-        )
-        .expect("Initialize contract");
+#[derive(Serialize, SchemaType)]
+pub struct Cis2TokensBalanceOfParameter {
+    #[concordium(size_length = 2)]
+    pub queries: Vec<Cis2TokensBalanceOfQuery>,
+}
 
-    (
+/// The token balance of a public key in the smart wallet.
+fn wallet_balance_of(
+    chain: &mut Chain,
+    smart_contract_wallet: ContractAddress,
+    cis2_token_contract_address: ContractAddress,
+    public_key: PublicKeyEd25519,
+) -> u64 {
+    wallet_token_balance_of(
         chain,
-        smart_contract_wallet_init.contract_address,
-        cis2_token_contract_init.contract_address,
-        gona_stake_init.contract_address,
+        smart_contract_wallet,
+        cis2_token_contract_address,
+        TokenIdVec(Vec::new()),
+        public_key,
     )
 }
 
-fn alice_deposits_cis2_tokens_and_fund_pool(
+/// The balance of a public key in the smart wallet of a given token id.
+fn wallet_token_balance_of(
     chain: &mut Chain,
     smart_contract_wallet: ContractAddress,
     cis2_token_contract_address: ContractAddress,
-    alice_public_key: PublicKeyEd25519,
-    gona_stake_address: ContractAddress,
-) {
-    let mint_param: MintParam = MintParam {
-        owner: Address::Account(ALICE),
-        token_id: TOKEN_ID,
-        amount: TokenAmountU64(AIRDROP_TOKEN_AMOUNT),
+    token_id: TokenIdVec,
+    public_key: PublicKeyEd25519,
+) -> u64 {
+    let query = Cis2TokensBalanceOfParameter {
+        queries: vec![Cis2TokensBalanceOfQuery {
+            token_id,
+            cis2_token_contract_address,
+            public_key,
+        }],
     };
+    let invoke = chain
+        .contract_invoke(
+            ALICE,
+            ALICE_ADDR,
+            Energy::from(10000),
+            UpdateContractPayload {
+                amount: Amount::zero(),
+                receive_name: OwnedReceiveName::new_unchecked(
+                    "smart_contract_wallet.cis2BalanceOf".to_string(),
+                ),
+                address: smart_contract_wallet,
+                message: OwnedParameter::from_serial(&query).expect("Balance query params"),
+            },
+        )
+        .expect("Invoke cis2BalanceOf");
+    let balances: BalanceOfQueryResponse<TokenAmountU256> =
+        invoke.parse_return_value().expect("Balance response");
+    balances.0[0].0.as_u64()
+}
 
-    //Deposit tokens.
-    let _update = chain
-        .contract_update(
-            SIGNER,
+/// Signs a permit message with `signing_key`.
+fn sign_permit(
+    chain: &mut Chain,
+    gona_stake: ContractAddress,
+    signing_key: &ed25519_dalek::SigningKey,
+    message: PermitMessage,
+) -> PermitParam {
+    use ed25519_dalek::Signer;
+
+    let hash: [u8; 32] = chain
+        .contract_invoke(
             ALICE,
             ALICE_ADDR,
             Energy::from(10000),
             UpdateContractPayload {
                 amount: Amount::zero(),
-                receive_name: OwnedReceiveName::new_unchecked("gona_token.mint".to_string()),
-                address: cis2_token_contract_address,
-                message: OwnedParameter::from_serial(&mint_param).expect("Mint cis2 tokens params"),
+                receive_name: OwnedReceiveName::new_unchecked(
+                    "gona_stake.viewMessageHash".to_string(),
+                ),
+                address: gona_stake,
+                message: OwnedParameter::from_serial(&message).expect("Permit message"),
             },
         )
-        .expect("Should be able to deposit cis2 tokens");
+        .expect("Should be able to query viewMessageHash")
+        .parse_return_value()
+        .expect("Should return a hash");
+    PermitParam {
+        signature: SignatureEd25519(signing_key.sign(&hash).to_bytes()),
+        signer: PublicKeyEd25519(signing_key.verifying_key().to_bytes()),
+        message,
+    }
+}
 
-    // // Create a Transfer instance
-    let transfer_payload = concordium_cis2::Transfer {
-        token_id: TOKEN_ID,
-        amount: TokenAmountU64(ALICE_KEY_AMOUNT),
-        to: Receiver::Contract(
-            smart_contract_wallet,
-            OwnedEntrypointName::new_unchecked("depositCis2Tokens".into()),
-        ),
-        from: ALICE_ADDR,
-        data: AdditionalData::from(to_bytes(&alice_public_key)),
-    };
-    // Create a Transfer instance
-    let transfer_pool_payload = concordium_cis2::Transfer {
-        token_id: TOKEN_ID,
-        amount: TokenAmountU64(POOL_REWARD_AMOUNT),
-        to: Receiver::Contract(
-            gona_stake_address,
-            OwnedEntrypointName::new_unchecked("depositCis2Tokens".into()),
-        ),
-        from: ALICE_ADDR,
-        data: AdditionalData::empty(),
+/// Charlie sponsors a permit.
+fn permit(
+    chain: &mut Chain,
+    gona_stake: ContractAddress,
+    param: &PermitParam,
+) -> Result<ContractInvokeSuccess, ContractInvokeError> {
+    chain.contract_update(
+        SIGNER,
+        CHARLIE,
+        CHARLIE_ADDR,
+        Energy::from(10000),
+        UpdateContractPayload {
+            amount: Amount::zero(),
+            receive_name: OwnedReceiveName::new_unchecked("gona_stake.permit".to_string()),
+            address: gona_stake,
+            message: OwnedParameter::from_serial(param).expect("Permit params"),
+        },
+    )
+}
+
+/// Signs a permit for `entry_point` with the next nonce of `signing_key` and
+/// has Charlie sponsor it.
+fn permit_as(
+    chain: &mut Chain,
+    gona_stake: ContractAddress,
+    signing_key: &ed25519_dalek::SigningKey,
+    entry_point: &str,
+    payload: Vec<u8>,
+) -> Result<ContractInvokeSuccess, ContractInvokeError> {
+    let key = PublicKeyEd25519(signing_key.verifying_key().to_bytes());
+    let message = PermitMessage {
+        contract_address: gona_stake,
+        nonce: nonce_of(chain, gona_stake, key),
+        timestamp: chain
+            .block_time()
+            .checked_add(Duration::from_days(1))
+            .unwrap(),
+        entry_point: OwnedEntrypointName::new_unchecked(entry_point.to_string()),
+        payload,
     };
-    let mut transfers = Vec::new();
-    transfers.push(transfer_payload);
-    transfers.push(transfer_pool_payload);
+    let param = sign_permit(chain, gona_stake, signing_key, message);
+    permit(chain, gona_stake, &param)
+}
 
-    let payload = TransferParams::from(transfers);
-    // Deposit tokens.
-    let _update = chain
-        .contract_update(
-            SIGNER,
+fn nonce_of(chain: &mut Chain, gona_stake: ContractAddress, key: PublicKeyEd25519) -> u64 {
+    let nonces: NonceOfQueryResponse = chain
+        .contract_invoke(
             ALICE,
             ALICE_ADDR,
             Energy::from(10000),
             UpdateContractPayload {
                 amount: Amount::zero(),
-                receive_name: OwnedReceiveName::new_unchecked("gona_token.transfer".to_string()),
-                address: cis2_token_contract_address,
-                message: OwnedParameter::from_serial(&payload).expect("Mint cis2 tokens params"),
+                receive_name: OwnedReceiveName::new_unchecked("gona_stake.nonceOf".to_string()),
+                address: gona_stake,
+                message: OwnedParameter::from_serial(&NonceOfQueryParams { queries: vec![key] })
+                    .expect("Nonce params"),
             },
         )
-        .expect("Should be able to deposit cis2 tokens");
+        .expect("Should be able to query nonceOf")
+        .parse_return_value()
+        .expect("Nonce response");
+    nonces.0[0]
 }
 
-fn get_stake_query(
+/// Transfers receipt tokens of the gona stake contract from `sender`.
+fn transfer_receipts(
     chain: &mut Chain,
+    sender: AccountAddress,
     gona_stake: ContractAddress,
-    alice_public_key: PublicKeyEd25519,
-) -> StakeQuery {
-    let staker = alice_public_key;
-    let invoke = chain
+    to: Receiver,
+    amount: u64,
+) -> Result<ContractInvokeSuccess, ContractInvokeError> {
+    transfer_stake_tokens(chain, sender, gona_stake, RECEIPT_TOKEN_ID, to, amount)
+}
+
+/// Transfers receipt or position tokens of the gona stake contract from
+/// `sender`.
+fn transfer_stake_tokens(
+    chain: &mut Chain,
+    sender: AccountAddress,
+    gona_stake: ContractAddress,
+    token_id: TokenIdVec,
+    to: Receiver,
+    amount: u64,
+) -> Result<ContractInvokeSuccess, ContractInvokeError> {
+    let payload = TransferParams::from(vec![concordium_cis2::Transfer {
+        token_id,
+        amount: TokenAmountU64(amount),
+        from: Address::Account(sender),
+        to,
+        data: AdditionalData::empty(),
+    }]);
+    chain.contract_update(
+        SIGNER,
+        sender,
+        Address::Account(sender),
+        Energy::from(20000),
+        UpdateContractPayload {
+            amount: Amount::zero(),
+            receive_name: OwnedReceiveName::new_unchecked("gona_stake.transfer".to_string()),
+            address: gona_stake,
+            message: OwnedParameter::from_serial(&payload).expect("Transfer params"),
+        },
+    )
+}
+
+fn receipt_balance_of(chain: &mut Chain, gona_stake: ContractAddress, owner: Address) -> u64 {
+    stake_token_balance_of(chain, gona_stake, RECEIPT_TOKEN_ID, owner)
+}
+
+/// The balance of a receipt or position token of the gona stake contract.
+fn stake_token_balance_of(
+    chain: &mut Chain,
+    gona_stake: ContractAddress,
+    token_id: TokenIdVec,
+    owner: Address,
+) -> u64 {
+    let query = BalanceOfQueryParams {
+        queries: vec![BalanceOfQuery {
+            token_id,
+            address: owner,
+        }],
+    };
+    let balances: BalanceOfQueryResponse<TokenAmountU64> = chain
         .contract_invoke(
             ALICE,
             ALICE_ADDR,
             Energy::from(10000),
             UpdateContractPayload {
                 amount: Amount::zero(),
-                receive_name: OwnedReceiveName::new_unchecked(
-                    "gona_stake.get_stake_info".to_string(),
-                ),
+                receive_name: OwnedReceiveName::new_unchecked("gona_stake.balanceOf".to_string()),
                 address: gona_stake,
-                message: OwnedParameter::from_serial(&staker).expect("Damn, wth happened"),
+                message: OwnedParameter::from_serial(&query).expect("Balance query params"),
             },
         )
-        .expect("Invoke Stake Query");
-    let sq: StakeQuery = invoke
+        .expect("Invoke balanceOf")
         .parse_return_value()
-        .expect("Damn, the hell happened");
-    sq
+        .expect("Balance response");
+    balances.0[0].0
 }
 
-fn view_reward_amount(chain: &mut Chain, gona_stake: ContractAddress) -> u64 {
-    let invoke = chain
+/// The staked tokens `shares` receipt tokens are worth.
+fn share_value(shares: u64, total_assets: u64, total_shares: u64) -> u64 {
+    (shares as u128 * total_assets as u128 / total_shares as u128) as u64
+}
+
+fn view_liquid_pool(chain: &mut Chain, gona_stake: ContractAddress) -> LiquidPool {
+    chain
         .contract_invoke(
             ALICE,
             ALICE_ADDR,
@@ -630,26 +3644,27 @@ fn view_reward_amount(chain: &mut Chain, gona_stake: ContractAddress) -> u64 {
             UpdateContractPayload {
                 amount: Amount::zero(),
                 receive_name: OwnedReceiveName::new_unchecked(
-                    "gona_stake.view_reward_volume".to_string(),
+                    "gona_stake.view_liquid_pool".to_string(),
                 ),
                 address: gona_stake,
                 message: OwnedParameter::empty(),
             },
         )
-        .expect("Invoke Stake Query");
-    let res: u64 = invoke
+        .expect("Invoke view_liquid_pool")
         .parse_return_value()
-        .expect("Damn, the hell happened");
-    res
+        .expect("Liquid pool response")
 }
 
-fn calculate_reward(
+fn staker_operator_of(
     chain: &mut Chain,
     gona_stake: ContractAddress,
-    alice_public_key: PublicKeyEd25519,
-) -> RewardResult {
-    let staker = alice_public_key;
-    let invoke = chain
+    owner: Staker,
+    address: Address,
+) -> bool {
+    let query = StakerOperatorOfQueryParams {
+        queries: vec![StakerOperatorOfQuery { owner, address }],
+    };
+    let response: OperatorOfQueryResponse = chain
         .contract_invoke(
             ALICE,
             ALICE_ADDR,
@@ -657,51 +3672,184 @@ fn calculate_reward(
             UpdateContractPayload {
                 amount: Amount::zero(),
                 receive_name: OwnedReceiveName::new_unchecked(
-                    "gona_stake.calculate_rewards".to_string(),
+                    "gona_stake.staker_operator_of".to_string(),
                 ),
                 address: gona_stake,
-                message: OwnedParameter::from_serial(&staker)
-                    .expect("Staker could not be serialized"),
+                message: OwnedParameter::from_serial(&query).expect("Operator query params"),
             },
         )
-        .expect("Invoke Stake Query");
-    let res: RewardResult = invoke
+        .expect("Should be able to query staker_operator_of")
         .parse_return_value()
-        .expect("Damn, the hell happened");
-    res
+        .expect("Operator response");
+    response.0[0]
 }
 
-fn calculate_percent(amount: u64, weight: u32, decimals: u8) -> u64 {
-    (amount * (weight as u64)) / (100 * (10_i32.pow(decimals as u32) as u64))
+fn compound(
+    chain: &mut Chain,
+    gona_stake: ContractAddress,
+    positions: Vec<PositionId>,
+) -> Result<ContractInvokeSuccess, ContractInvokeError> {
+    chain.contract_update(
+        SIGNER,
+        CHARLIE,
+        CHARLIE_ADDR,
+        Energy::from(10000),
+        UpdateContractPayload {
+            amount: Amount::zero(),
+            receive_name: OwnedReceiveName::new_unchecked("gona_stake.compound".to_string()),
+            address: gona_stake,
+            message: OwnedParameter::from_serial(&CompoundParams { positions })
+                .expect("Compound params"),
+        },
+    )
+}
+
+fn view_epoch(chain: &mut Chain, gona_stake: ContractAddress) -> EpochInfo {
+    chain
+        .contract_invoke(
+            ALICE,
+            ALICE_ADDR,
+            Energy::from(10000),
+            UpdateContractPayload {
+                amount: Amount::zero(),
+                receive_name: OwnedReceiveName::new_unchecked("gona_stake.view_epoch".to_string()),
+                address: gona_stake,
+                message: OwnedParameter::empty(),
+            },
+        )
+        .expect("Epochs should be configured")
+        .parse_return_value()
+        .expect("Epoch info")
 }
 
+/// The rewards `amount` earns over `seconds` at the daily `WEIGHT`.
+fn reference_rewards(amount: u64, seconds: u64) -> u64 {
+    let rewards = U256::from(amount) * U256::from(WEIGHT) * U256::from(seconds)
+        / (U256::from(100) * U256::exp10(DECIMALS.into()) * U256::from(24 * 60 * 60));
+    rewards.as_u64()
+}
 
-fn release_stake(
-    chain: &mut Chain,
-    gona_stake: ContractAddress,
-    alice_public_key: PublicKeyEd25519,
-    _signing_key: ed25519_dalek::SigningKey,
-    amount: u64,
-) {
-    let staker = alice_public_key;
+fn view_settings(chain: &mut Chain, gona_stake: ContractAddress) -> Settings {
+    view(chain, gona_stake, "view_settings", &())
+}
 
-    let param = UnstakeParam {
-        amount: TokenAmountU64(amount),
-        staker: staker.clone(),
-    };
+fn view_staked(chain: &mut Chain, gona_stake: ContractAddress, staker: Staker) -> StakedTotals {
+    view(chain, gona_stake, "view_staked", &staker)
+}
 
-    let _invoke = chain
-        .contract_update(
-            SIGNER,
+fn update_settings(chain: &mut Chain, gona_stake: ContractAddress, update: SettingUpdate) {
+    admin_update(chain, gona_stake, "update_settings", &update);
+}
+
+/// Invokes the view `entrypoint` of the staking contract.
+fn view<P: Serial, R: Deserial>(
+    chain: &mut Chain,
+    gona_stake: ContractAddress,
+    entrypoint: &str,
+    param: &P,
+) -> R {
+    chain
+        .contract_invoke(
             ALICE,
             ALICE_ADDR,
             Energy::from(10000),
             UpdateContractPayload {
                 amount: Amount::zero(),
-                receive_name: OwnedReceiveName::new_unchecked("gona_stake.unstake".to_string()),
+                receive_name: OwnedReceiveName::new_unchecked(format!("gona_stake.{entrypoint}")),
                 address: gona_stake,
-                message: OwnedParameter::from_serial(&param).expect("Damn, wth happened"),
+                message: OwnedParameter::from_serial(param).expect("View params"),
             },
         )
-        .expect("Invoke Stake Query");
+        .expect("View should succeed")
+        .parse_return_value()
+        .expect("View return value")
+}
+
+fn quote_unstake(
+    chain: &mut Chain,
+    gona_stake: ContractAddress,
+    position: PositionId,
+    amount: u64,
+) -> UnstakeQuote {
+    view(
+        chain,
+        gona_stake,
+        "quote_unstake",
+        &QuoteUnstakeParam {
+            position,
+            amount: TokenAmountU64(amount),
+        },
+    )
+}
+
+fn withdraw_fees(
+    chain: &mut Chain,
+    sender: AccountAddress,
+    gona_stake: ContractAddress,
+    param: &WithdrawFeesParam,
+) -> Result<ContractInvokeSuccess, ContractInvokeError> {
+    chain.contract_update(
+        SIGNER,
+        sender,
+        Address::Account(sender),
+        Energy::from(10000),
+        UpdateContractPayload {
+            amount: Amount::zero(),
+            receive_name: OwnedReceiveName::new_unchecked("gona_stake.withdraw_fees".to_string()),
+            address: gona_stake,
+            message: OwnedParameter::from_serial(param).expect("Withdraw fees params"),
+        },
+    )
+}
+
+fn view_referral_stats(
+    chain: &mut Chain,
+    gona_stake: ContractAddress,
+    referrer: Staker,
+) -> ReferralStats {
+    view(chain, gona_stake, "view_referral_stats", &referrer)
+}
+
+fn claim_referral_rewards(
+    chain: &mut Chain,
+    sender: AccountAddress,
+    gona_stake: ContractAddress,
+) -> Result<ContractInvokeSuccess, ContractInvokeError> {
+    chain.contract_update(
+        SIGNER,
+        sender,
+        Address::Account(sender),
+        Energy::from(10000),
+        UpdateContractPayload {
+            amount: Amount::zero(),
+            receive_name: OwnedReceiveName::new_unchecked(
+                "gona_stake.claim_referral_rewards".to_string(),
+            ),
+            address: gona_stake,
+            message: OwnedParameter::from_serial(&None::<ContractAddress>).expect("Wallet params"),
+        },
+    )
+}
+
+fn view_staker_access(
+    chain: &mut Chain,
+    gona_stake: ContractAddress,
+    staker: Staker,
+) -> StakerAccess {
+    view(chain, gona_stake, "view_staker_access", &staker)
+}
+
+/// Records the staking events of `update` with the time of its block.
+fn record_events(
+    events: &mut Vec<(Timestamp, StakingEvent)>,
+    chain: &Chain,
+    update: &ContractInvokeSuccess,
+    gona_stake: ContractAddress,
+) {
+    let time = chain.block_time();
+    events.extend(
+        staking_events(update, gona_stake)
+            .into_iter()
+            .map(|event| (time, event)),
+    );
 }